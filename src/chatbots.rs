@@ -1,2 +1,219 @@
+use core::{future::Future, time::Duration};
+use std::{env, fs};
+
+use reqwest::{header, Certificate, Client, Proxy, Response, StatusCode};
+use serde::Serialize;
+use serde_json::Value;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::Config, redact_secrets, ChatbotChatError, ChatbotCreationError,
+    Message, SECRET_PARAMS,
+};
+
 pub mod dummy;
 pub mod gemini;
+pub mod huggingface;
+pub mod openai_compat;
+pub mod perplexity;
+pub mod registry;
+
+const PROXY_ENV_VARS: [&str; 4] =
+    ["HTTPS_PROXY", "https_proxy", "HTTP_PROXY", "http_proxy"];
+
+/// Builds the HTTP client a chatbot's `create` uses.
+///
+/// Every HTTP chatbot also has a `with_client` constructor taking a client
+/// and base URL directly, so it can be pointed at a mock server or given its
+/// own timeouts.
+#[inline]
+pub fn build_client(config: &Config) -> Result<Client, ChatbotCreationError> {
+    let mut builder = Client::builder();
+
+    let proxy_url = config.proxy_url.clone().or_else(|| {
+        PROXY_ENV_VARS
+            .iter()
+            .find_map(|var| env::var(var).ok())
+            .filter(|url| !url.is_empty())
+    });
+
+    if let Some(proxy_url) = proxy_url {
+        let proxy = Proxy::all(proxy_url)
+            .map_err(ChatbotCreationError::InvalidProxy)?;
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(ca_cert_path) = config.ca_cert_path.as_ref() {
+        let pem =
+            fs::read(ca_cert_path).map_err(ChatbotCreationError::CaCert)?;
+        let cert = Certificate::from_pem(&pem)
+            .map_err(ChatbotCreationError::InvalidCaCert)?;
+        builder = builder.add_root_certificate(cert);
+    }
+
+    if config.danger_accept_invalid_certs.unwrap_or_default() {
+        tracing::warn!(
+            "TLS certificate verification is disabled \
+             (danger_accept_invalid_certs)"
+        );
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    builder.build().map_err(ChatbotCreationError::HttpClient)
+}
+
+/// Prints `body` as pretty JSON at debug level if `log_requests` is set in
+/// the config, with anything that looks like a credential masked.
+#[inline]
+pub fn log_request_body<T: Serialize>(enabled: bool, body: &T) {
+    if !enabled {
+        return;
+    }
+
+    match serde_json::to_value(body) {
+        Ok(mut value) => {
+            mask_secret_fields(&mut value);
+            let pretty =
+                serde_json::to_string_pretty(&value).unwrap_or_default();
+            tracing::debug!(body = %redact_secrets(&pretty), "Request body");
+        }
+        Err(err) => tracing::debug!(%err, "Failed to serialize request body"),
+    }
+}
+
+fn mask_secret_fields(value: &mut Value) {
+    match *value {
+        Value::Object(ref mut fields) => {
+            for (name, field) in fields.iter_mut() {
+                let is_secret = name.eq_ignore_ascii_case("authorization")
+                    || SECRET_PARAMS
+                        .iter()
+                        .any(|param| name.eq_ignore_ascii_case(param));
+                if is_secret {
+                    *field = Value::String("[REDACTED]".to_owned());
+                } else {
+                    mask_secret_fields(field);
+                }
+            }
+        }
+        Value::Array(ref mut items) => {
+            items.iter_mut().for_each(mask_secret_fields);
+        }
+        Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => {}
+    }
+}
+
+/// Maps HTTP error statuses that have a dedicated error variant.
+///
+/// Any other status is left to the provider, which usually has a more
+/// specific error in the response body.
+#[inline]
+pub fn check_status(
+    response: &Response,
+    provider: &str,
+) -> Result<(), ChatbotChatError> {
+    let status = response.status();
+
+    if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        return Err(ChatbotChatError::Unauthorized {
+            provider: provider.to_owned(),
+        });
+    }
+
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+            .map(Duration::from_secs);
+
+        return Err(ChatbotChatError::RateLimited { retry_after });
+    }
+
+    Ok(())
+}
+
+/// Runs `request` until it finishes or `cancel` is cancelled, in which case
+/// the request is dropped along with its connection.
+#[inline]
+pub async fn until_cancelled<T, F>(
+    cancel: &CancellationToken,
+    request: F,
+) -> Result<T, ChatbotChatError>
+where
+    F: Future<Output = Result<T, ChatbotChatError>> + Send,
+{
+    cancel
+        .run_until_cancelled(request)
+        .await
+        .unwrap_or(Err(ChatbotChatError::Cancelled))
+}
+
+/// Rejects attachments on the message about to be sent.
+///
+/// Attachments on earlier turns are dropped instead, so switching away from
+/// a multimodal chatbot keeps the conversation usable.
+#[inline]
+pub fn reject_attachments(
+    messages: &[Message],
+    provider: &str,
+) -> Result<(), ChatbotChatError> {
+    if messages
+        .last()
+        .is_some_and(|msg| !msg.attachments.is_empty())
+    {
+        return Err(ChatbotChatError::AttachmentsUnsupported {
+            provider: provider.to_owned(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Splits a leading `<think>...</think>` block, as sent by open reasoning
+/// models, off a reply.
+#[inline]
+#[must_use]
+pub fn split_reasoning(content: String) -> (Option<String>, String) {
+    let Some(rest) = content.trim_start().strip_prefix("<think>") else {
+        return (None, content);
+    };
+    let Some((reasoning, answer)) = rest.split_once("</think>") else {
+        return (None, content);
+    };
+
+    (
+        Some(reasoning.trim().to_owned()),
+        answer.trim_start().to_owned(),
+    )
+}
+
+/// Joins runs of messages with the same role into a single message.
+///
+/// Providers such as Gemini reject conversations whose turns do not
+/// alternate, which can happen after a cancelled or failed request.
+#[inline]
+pub fn merge_consecutive_roles<'messages, I>(messages: I) -> Vec<Message>
+where
+    I: IntoIterator<Item = &'messages Message>,
+{
+    let mut merged: Vec<Message> = Vec::new();
+
+    for msg in messages {
+        match merged.last_mut() {
+            Some(last)
+                if last.role == msg.role
+                    && last.tool.is_none()
+                    && msg.tool.is_none() =>
+            {
+                last.content.push_str("\n\n");
+                last.content.push_str(&msg.content);
+                last.attachments.extend(msg.attachments.iter().cloned());
+            }
+            _ => merged.push(msg.clone()),
+        }
+    }
+
+    merged
+}
@@ -1,31 +1,67 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, fs, path::Path};
+
 use async_trait::async_trait;
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    Chatbot, ChatbotChatError, ChatbotCreationError, InvalidModelError, Role,
+    chatbots::{reject_attachments, until_cancelled},
+    config::Config,
+    ChatResponse, Chatbot, ChatbotChatError, ChatbotCreationError,
+    GenerationParams, InvalidModelError, ModelInfo, Role,
 };
 
-const AVAILABLE_MODELS: [&str; 2] = ["1", "2"];
+pub const DEFAULT_MODEL: &str = "1";
+
+const AVAILABLE_MODELS: [&str; 2] = [DEFAULT_MODEL, "2"];
 
 #[non_exhaustive]
 #[derive(Default)]
 pub struct DummyChatbot {
     model: String,
+    script: Vec<String>,
+    next_line: AtomicUsize,
 }
 
-impl DummyChatbot {}
+impl DummyChatbot {
+    fn load_script(path: &Path) -> Result<Vec<String>, ChatbotCreationError> {
+        let script = fs::read_to_string(path)
+            .map_err(ChatbotCreationError::DummyScript)?;
+
+        Ok(script
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.replace("\\n", "\n"))
+            .collect())
+    }
+}
 
 #[async_trait]
 impl Chatbot for DummyChatbot {
     #[inline]
-    fn create(
+    async fn create(
         model: String,
         _api_key: Option<String>,
+        config: &Config,
     ) -> Result<Box<dyn Chatbot>, ChatbotCreationError> {
-        if AVAILABLE_MODELS.contains(&model.as_str()) {
-            Ok(Box::new(Self { model }))
-        } else {
-            Err(ChatbotCreationError::UnknownModel)
+        if !AVAILABLE_MODELS.contains(&model.as_str()) {
+            return Err(ChatbotCreationError::UnknownModel);
         }
+
+        let script_path = config
+            .dummy_script
+            .clone()
+            .or_else(|| env::var_os("DUMMY_SCRIPT").map(Into::into));
+        let script = match script_path {
+            Some(path) => Self::load_script(&path)?,
+            None => Vec::new(),
+        };
+
+        Ok(Box::new(Self {
+            model,
+            script,
+            next_line: AtomicUsize::new(0),
+        }))
     }
 
     #[inline]
@@ -34,7 +70,7 @@ impl Chatbot for DummyChatbot {
     }
 
     #[inline]
-    fn model(&self) -> &'static str {
+    fn model(&self) -> &str {
         match self.model.as_str() {
             "1" => "Model 1",
             "2" => "Model 2",
@@ -43,8 +79,21 @@ impl Chatbot for DummyChatbot {
     }
 
     #[inline]
-    fn available_models(&self) -> &[&str] {
-        &AVAILABLE_MODELS
+    fn model_id(&self) -> &str {
+        &self.model
+    }
+
+    #[inline]
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo::new(None, false, true, false)
+    }
+
+    #[inline]
+    fn available_models(&self) -> Vec<String> {
+        AVAILABLE_MODELS
+            .iter()
+            .map(|&model| model.to_owned())
+            .collect()
     }
 
     #[inline]
@@ -60,11 +109,23 @@ impl Chatbot for DummyChatbot {
         }
     }
 
+    #[inline]
+    fn generation_params_mut(&mut self) -> Option<&mut GenerationParams> {
+        None
+    }
+
     #[inline]
     async fn send_message(
         &self,
         messages: &[crate::Message],
-    ) -> Result<String, ChatbotChatError> {
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        reject_attachments(messages, self.name())?;
+
+        let line = self.next_line.fetch_add(1, Ordering::Relaxed);
+        if let Some(reply) = self.script.get(line) {
+            return Ok(ChatResponse::new(reply.clone(), None));
+        }
+
         let msg = messages.last().map_or_else(
             || "Dummy response to empty conversation.".to_owned(),
             |last_msg| {
@@ -76,6 +137,27 @@ impl Chatbot for DummyChatbot {
             },
         );
 
-        Ok(msg)
+        Ok(ChatResponse::new(msg, None))
+    }
+
+    #[inline]
+    async fn send_message_cancellable(
+        &self,
+        messages: &[crate::Message],
+        cancel: &CancellationToken,
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        until_cancelled(cancel, self.send_message(messages)).await
+    }
+
+    #[inline]
+    async fn stream_message(
+        &self,
+        messages: &[crate::Message],
+        on_chunk: &mut (dyn for<'chunk> FnMut(&'chunk str) + Send),
+        cancel: &CancellationToken,
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        let response = self.send_message_cancellable(messages, cancel).await?;
+        on_chunk(&response.content);
+        Ok(response)
     }
 }
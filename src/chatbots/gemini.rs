@@ -1,34 +1,206 @@
 use alloc::borrow::Cow;
-use std::env;
 
 use async_trait::async_trait;
-use reqwest::Client;
+use futures::StreamExt as _;
+use reqwest::{Client, Response};
 use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    Chatbot, ChatbotChatError, ChatbotCreationError, InvalidModelError, Role,
+    chatbots::{
+        build_client, check_status, log_request_body, merge_consecutive_roles,
+        until_cancelled,
+    },
+    config::Config,
+    redact_secrets, Attachment, ChatResponse, Chatbot, ChatbotChatError,
+    ChatbotCreationError, GenerationParams, InvalidModelError, Message,
+    ModelInfo, Role, Usage,
 };
+#[cfg(feature = "tools")]
+use crate::{ToolCall, ToolPayload};
+
+pub const DEFAULT_MODEL: &str = "gemini-1.5-flash";
 
 const GEMINI_BASE_URL: &str =
-    "https://generativelanguage.googleapis.com/v1beta/models/";
-
-const AVAILABLE_MODELS: [&str; 5] = [
-    "gemini-2.0-flash-exp",
-    "gemini-1.5-flash",
-    "gemini-1.5-flash-8b",
-    "gemini-1.5-pro",
-    "gemini-1.0-pro",
+    "https://generativelanguage.googleapis.com/v1beta/models";
+
+const CONTEXT_1M: usize = 1 << 20;
+const CONTEXT_2M: usize = 2 << 20;
+
+const MODELS: [ModelSpec; 8] = [
+    ModelSpec::new("gemini-2.5-pro", "2.5 Pro", CONTEXT_1M).thinking(),
+    ModelSpec::new("gemini-2.5-flash", "2.5 Flash", CONTEXT_1M).thinking(),
+    ModelSpec::new("gemini-2.5-flash-lite", "2.5 Flash-Lite", CONTEXT_1M)
+        .thinking(),
+    ModelSpec::new("gemini-2.0-flash", "2.0 Flash", CONTEXT_1M),
+    ModelSpec::new("gemini-2.0-flash-lite", "2.0 Flash-Lite", CONTEXT_1M),
+    ModelSpec::new("gemini-1.5-pro", "1.5 Pro", CONTEXT_2M),
+    ModelSpec::new("gemini-1.5-flash", "1.5 Flash", CONTEXT_1M),
+    ModelSpec::new("gemini-1.5-flash-8b", "1.5 Flash-8B", CONTEXT_1M),
 ];
 
+struct ModelSpec {
+    id: &'static str,
+    display_name: &'static str,
+    context_window: usize,
+    thinking: bool,
+}
+
+impl ModelSpec {
+    const fn new(
+        id: &'static str,
+        display_name: &'static str,
+        context_window: usize,
+    ) -> Self {
+        Self {
+            id,
+            display_name,
+            context_window,
+            thinking: false,
+        }
+    }
+
+    /// Marks a model that can send summaries of its thoughts.
+    const fn thinking(mut self) -> Self {
+        self.thinking = true;
+        self
+    }
+
+    /// Looks a model up by id or, ignoring case, by its display name.
+    fn find(name: &str) -> Option<&'static Self> {
+        MODELS.iter().find(|spec| {
+            spec.id == name || spec.display_name.eq_ignore_ascii_case(name)
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiInlineData<'data> {
+    mime_type: Cow<'data, str>,
+    data: Cow<'data, str>,
+}
+
+#[cfg(feature = "tools")]
+#[derive(Serialize, Deserialize)]
+struct GeminiFunctionCall {
+    name: String,
+    #[serde(default)]
+    args: serde_json::Value,
+}
+
+#[cfg(feature = "tools")]
+#[derive(Serialize, Deserialize)]
+struct GeminiFunctionResponse {
+    name: String,
+    response: serde_json::Value,
+}
+
 #[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct GeminiPart<'text> {
-    text: Cow<'text, str>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    text: Option<Cow<'text, str>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    inline_data: Option<GeminiInlineData<'text>>,
+    #[serde(default, skip_serializing)]
+    thought: bool,
+    #[cfg(feature = "tools")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    function_call: Option<GeminiFunctionCall>,
+    #[cfg(feature = "tools")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    function_response: Option<GeminiFunctionResponse>,
+}
+
+impl<'text> GeminiPart<'text> {
+    const fn empty() -> Self {
+        Self {
+            text: None,
+            inline_data: None,
+            thought: false,
+            #[cfg(feature = "tools")]
+            function_call: None,
+            #[cfg(feature = "tools")]
+            function_response: None,
+        }
+    }
+
+    fn text(text: &'text str) -> Self {
+        Self {
+            text: Some(Cow::Borrowed(text)),
+            ..Self::empty()
+        }
+    }
+
+    fn attachment(attachment: &'text Attachment) -> Self {
+        Self {
+            inline_data: Some(GeminiInlineData {
+                mime_type: Cow::Borrowed(&attachment.mime_type),
+                data: Cow::Borrowed(&attachment.data),
+            }),
+            ..Self::empty()
+        }
+    }
+
+    #[cfg(feature = "tools")]
+    fn tool(payload: &ToolPayload) -> Self {
+        match *payload {
+            ToolPayload::Call(ref call) => Self {
+                function_call: Some(GeminiFunctionCall {
+                    name: call.name.clone(),
+                    args: call.arguments.clone(),
+                }),
+                ..Self::empty()
+            },
+            ToolPayload::Result(ref result) => Self {
+                function_response: Some(GeminiFunctionResponse {
+                    name: result.name.clone(),
+                    response: result.output.clone(),
+                }),
+                ..Self::empty()
+            },
+        }
+    }
+
+    fn for_message(msg: &'text Message) -> Vec<Self> {
+        let mut parts: Vec<Self> =
+            msg.attachments.iter().map(Self::attachment).collect();
+
+        #[cfg(feature = "tools")]
+        if let Some(payload) = msg.tool.as_ref() {
+            parts.push(Self::tool(payload));
+            if msg.content.is_empty() {
+                return parts;
+            }
+        }
+
+        parts.push(Self::text(&msg.content));
+        parts
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum GeminiRole {
+    User,
+    Model,
+}
+
+impl From<Role> for GeminiRole {
+    #[inline]
+    fn from(role: Role) -> Self {
+        match role {
+            Role::System | Role::User | Role::Tool => Self::User,
+            Role::Assistant => Self::Model,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 struct GeminiMessage<'text> {
-    role: Role,
-    #[serde(borrow)]
+    role: GeminiRole,
+    #[serde(borrow, default)]
     parts: Vec<GeminiPart<'text>>,
 }
 
@@ -38,59 +210,311 @@ struct SystemInstruction<'text> {
 }
 
 #[derive(Serialize)]
-struct GeminiRequest<'system, 'text> {
+#[serde(rename_all = "camelCase")]
+struct GeminiThinkingConfig {
+    include_thoughts: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiGenerationConfig<'params> {
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    stop_sequences: &'params [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking_config: Option<GeminiThinkingConfig>,
+}
+
+#[derive(Serialize)]
+struct GeminiRequest<'system, 'text, 'params> {
     system_instruction: Option<SystemInstruction<'system>>,
     contents: Vec<GeminiMessage<'text>>,
+    #[serde(
+        rename = "generationConfig",
+        skip_serializing_if = "Option::is_none"
+    )]
+    generation_config: Option<GeminiGenerationConfig<'params>>,
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct GeminiCandidate<'text> {
     #[serde(borrow)]
-    content: GeminiMessage<'text>,
+    content: Option<GeminiMessage<'text>>,
+    finish_reason: Option<String>,
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiPromptFeedback {
+    block_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiUsage {
+    #[serde(default)]
+    prompt_token_count: u32,
+    #[serde(default)]
+    candidates_token_count: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct GeminiResponse<'text> {
-    #[serde(borrow)]
+    #[serde(borrow, default)]
     candidates: Vec<GeminiCandidate<'text>>,
+    prompt_feedback: Option<GeminiPromptFeedback>,
+    usage_metadata: Option<GeminiUsage>,
 }
 
 #[non_exhaustive]
 pub struct GeminiChatbot {
     api_key: String,
-    model: String,
+    model: &'static ModelSpec,
+    base_url: String,
     url: String,
     client: Client,
+    params: GenerationParams,
+    log_requests: bool,
 }
 
-#[async_trait]
-impl Chatbot for GeminiChatbot {
+impl GeminiChatbot {
     #[inline]
-    fn create(
-        model: String,
-        api_key: Option<String>,
-    ) -> Result<Box<dyn Chatbot>, ChatbotCreationError> {
-        let api_key = if let Some(api_key) = api_key {
-            api_key
-        } else {
-            env::var("GEMINI_API_KEY")?
+    pub fn model_ids() -> impl Iterator<Item = &'static str> {
+        MODELS.iter().map(|spec| spec.id)
+    }
+
+    /// `base_url` takes the place of `gemini_base_url` and should end in
+    /// the `models` collection, e.g. `https://host/v1beta/models`.
+    #[inline]
+    pub fn with_client(
+        model: &str,
+        api_key: String,
+        base_url: &str,
+        client: Client,
+    ) -> Result<Self, ChatbotCreationError> {
+        let model =
+            ModelSpec::find(model).ok_or(ChatbotCreationError::UnknownModel)?;
+        let base_url = base_url.trim_end_matches('/').to_owned();
+        let url = Self::generate_url(&base_url, model);
+
+        Ok(Self {
+            api_key,
+            model,
+            base_url,
+            url,
+            client,
+            params: GenerationParams::default(),
+            log_requests: false,
+        })
+    }
+
+    fn generate_url(base_url: &str, model: &ModelSpec) -> String {
+        format!("{base_url}/{}:generateContent", model.id)
+    }
+
+    fn turns(messages: &[Message]) -> Vec<Message> {
+        merge_consecutive_roles(
+            messages.iter().filter(|msg| msg.role != Role::System),
+        )
+    }
+
+    fn request_body<'text>(
+        &'text self,
+        messages: &'text [Message],
+        turns: &'text [Message],
+    ) -> GeminiRequest<'text, 'text, 'text> {
+        let system_parts: Vec<GeminiPart<'_>> = messages
+            .iter()
+            .filter(|msg| msg.role == Role::System)
+            .map(|msg| GeminiPart::text(&msg.content))
+            .collect();
+        let system = (!system_parts.is_empty()).then_some(SystemInstruction {
+            parts: system_parts,
+        });
+
+        let gemini_messages: Vec<GeminiMessage<'_>> = turns
+            .iter()
+            .map(|msg| GeminiMessage {
+                role: msg.role.into(),
+                parts: GeminiPart::for_message(msg),
+            })
+            .collect();
+
+        GeminiRequest {
+            system_instruction: system,
+            contents: gemini_messages,
+            generation_config: (!self.params.stop_sequences.is_empty()
+                || self.model.thinking)
+                .then(|| GeminiGenerationConfig {
+                    stop_sequences: &self.params.stop_sequences,
+                    thinking_config: self.model.thinking.then_some(
+                        GeminiThinkingConfig {
+                            include_thoughts: true,
+                        },
+                    ),
+                }),
+        }
+    }
+
+    async fn post(
+        &self,
+        url: &str,
+        request_body: &GeminiRequest<'_, '_, '_>,
+    ) -> Result<Response, ChatbotChatError> {
+        tracing::debug!(url = %redact_secrets(url), "Sending request");
+        log_request_body(self.log_requests, request_body);
+
+        let response = self
+            .client
+            .post(url)
+            .header("x-goog-api-key", &self.api_key)
+            .json(request_body)
+            .send()
+            .await
+            .map_err(|err| {
+                if err.is_timeout() {
+                    ChatbotChatError::Timeout
+                } else {
+                    ChatbotChatError::NetworkError(err)
+                }
+            })?;
+
+        tracing::debug!(status = %response.status(), "Received response");
+        check_status(&response, self.name())?;
+
+        Ok(response)
+    }
+}
+
+/// Joins the text of every answer part, or with `thoughts` of every thought
+/// summary part. Long replies are often split over several parts.
+fn joined_text(parts: &[GeminiPart<'_>], thoughts: bool) -> String {
+    parts
+        .iter()
+        .filter(|part| part.thought == thoughts)
+        .filter_map(|part| part.text.as_deref())
+        .collect()
+}
+
+/// Finish reasons for a candidate withheld by Gemini's filters. Any other
+/// reason, such as `MAX_TOKENS`, still comes with whatever text there was.
+const BLOCKED_FINISH_REASONS: [&str; 5] = [
+    "SAFETY",
+    "RECITATION",
+    "BLOCKLIST",
+    "PROHIBITED_CONTENT",
+    "SPII",
+];
+
+fn is_blocked(finish_reason: &str) -> bool {
+    BLOCKED_FINISH_REASONS.contains(&finish_reason)
+}
+
+/// Accumulates the server-sent events of `streamGenerateContent`.
+#[derive(Default)]
+struct GeminiStream {
+    content: String,
+    reasoning: String,
+    usage: Option<Usage>,
+    finish_reason: Option<String>,
+}
+
+impl GeminiStream {
+    fn push_line(
+        &mut self,
+        line: &[u8],
+        on_chunk: &mut (dyn for<'chunk> FnMut(&'chunk str) + Send),
+    ) -> Result<(), ChatbotChatError> {
+        let line = String::from_utf8_lossy(line);
+        let Some(data) = line.trim().strip_prefix("data:") else {
+            return Ok(());
         };
+        let data = data.trim();
+
+        let chunk: GeminiResponse<'_> =
+            serde_json::from_str(data).map_err(|err| {
+                tracing::debug!(%err, body = %data, "Failed to parse event");
+                ChatbotChatError::UnexpectedResponse {
+                    body: data.to_owned(),
+                }
+            })?;
+
+        if let Some(reason) = chunk
+            .prompt_feedback
+            .and_then(|feedback| feedback.block_reason)
+        {
+            return Err(ChatbotChatError::Blocked(reason));
+        }
+        if let Some(usage) = chunk.usage_metadata {
+            self.usage = Some(Usage::new(
+                usage.prompt_token_count,
+                usage.candidates_token_count,
+            ));
+        }
+
+        let Some(candidate) = chunk.candidates.into_iter().next() else {
+            return Ok(());
+        };
+        if candidate.finish_reason.is_some() {
+            self.finish_reason = candidate.finish_reason;
+        }
+        for part in candidate.content.into_iter().flat_map(|msg| msg.parts) {
+            let Some(text) = part.text else {
+                continue;
+            };
+            if part.thought {
+                self.reasoning.push_str(&text);
+            } else {
+                on_chunk(&text);
+                self.content.push_str(&text);
+            }
+        }
+
+        Ok(())
+    }
 
-        if !AVAILABLE_MODELS.contains(&model.as_str()) {
-            return Err(ChatbotCreationError::UnknownModel);
+    fn finish(self) -> Result<ChatResponse, ChatbotChatError> {
+        if let Some(reason) = self
+            .finish_reason
+            .as_deref()
+            .filter(|&reason| self.content.is_empty() && is_blocked(reason))
+        {
+            return Err(ChatbotChatError::Blocked(reason.to_owned()));
+        }
+        if self.content.is_empty() && self.finish_reason.is_none() {
+            return Err(ChatbotChatError::UnexpectedResponse {
+                body: String::new(),
+            });
         }
 
-        let url =
-            format!("{GEMINI_BASE_URL}{model}:generateContent?key={api_key}");
+        Ok(ChatResponse::new(self.content, self.usage)
+            .with_reasoning(Some(self.reasoning))
+            .with_finish_reason(self.finish_reason))
+    }
+}
 
-        let client = Client::new();
+#[async_trait]
+impl Chatbot for GeminiChatbot {
+    #[inline]
+    async fn create(
+        model: String,
+        api_key: Option<String>,
+        config: &Config,
+    ) -> Result<Box<dyn Chatbot>, ChatbotCreationError> {
+        let api_key = api_key.ok_or(ChatbotCreationError::ApiKeyMissing)?;
 
-        Ok(Box::new(Self {
+        let base_url =
+            config.gemini_base_url.as_deref().unwrap_or(GEMINI_BASE_URL);
+        let mut chatbot = Self::with_client(
+            &model,
             api_key,
-            model,
-            url,
-            client,
-        }))
+            base_url,
+            build_client(config)?,
+        )?;
+        chatbot.log_requests = config.log_requests.unwrap_or_default();
+
+        Ok(Box::new(chatbot))
     }
 
     #[inline]
@@ -99,27 +523,23 @@ impl Chatbot for GeminiChatbot {
     }
 
     #[inline]
-    fn model(&self) -> &'static str {
-        #[expect(
-            clippy::unreachable,
-            reason = r#"
-                `model` is validated on initialization and in `change_model`,
-                so it should always be a valid name.
-            "#
-        )]
-        match self.model.as_str() {
-            "gemini-2.0-flash-exp" => "2.0 Flash (Experimental)",
-            "gemini-1.5-flash" => "1.5 Flash",
-            "gemini-1.5-flash-8b" => "1.5 Flash-8B",
-            "gemini-1.5-pro" => "1.5 Pro",
-            "gemini-1.0-pro" => "1.0 Pro (Deprecated)",
-            _ => unreachable!(),
-        }
+    fn model(&self) -> &str {
+        self.model.display_name
+    }
+
+    #[inline]
+    fn model_id(&self) -> &str {
+        self.model.id
     }
 
     #[inline]
-    fn available_models(&self) -> &[&str] {
-        &AVAILABLE_MODELS
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo::new(Some(self.model.context_window), true, true, true)
+    }
+
+    #[inline]
+    fn available_models(&self) -> Vec<String> {
+        Self::model_ids().map(str::to_owned).collect()
     }
 
     #[inline]
@@ -127,94 +547,459 @@ impl Chatbot for GeminiChatbot {
         &mut self,
         new_model: String,
     ) -> Result<(), InvalidModelError> {
-        if !AVAILABLE_MODELS.contains(&new_model.as_str()) {
-            return Err(InvalidModelError);
-        }
+        self.model = ModelSpec::find(&new_model).ok_or(InvalidModelError)?;
 
-        self.model = new_model;
-
-        self.url = format!(
-            "{GEMINI_BASE_URL}{}:generateContent?key={}",
-            self.model, self.api_key
-        );
+        self.url = Self::generate_url(&self.base_url, self.model);
 
         Ok(())
     }
 
+    #[inline]
+    fn generation_params_mut(&mut self) -> Option<&mut GenerationParams> {
+        Some(&mut self.params)
+    }
+
     #[inline]
     async fn send_message(
         &self,
-        messages: &[crate::Message],
-    ) -> Result<String, ChatbotChatError> {
-        let system = messages.iter().find(|msg| msg.role == Role::System).map(
-            |system_prompt| SystemInstruction {
-                parts: vec![GeminiPart {
-                    text: Cow::Borrowed(&system_prompt.content),
-                }],
-            },
-        );
+        messages: &[Message],
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        let turns = Self::turns(messages);
+        let request_body = self.request_body(messages, &turns);
+        let resp_stream = self.post(&self.url, &request_body).await?;
 
-        let gemini_messages: Vec<GeminiMessage<'_>> = messages
-            .iter()
-            .filter(|msg| msg.role != Role::System)
-            .map(|msg| GeminiMessage {
-                role: msg.role,
-                parts: vec![GeminiPart {
-                    text: Cow::Borrowed(&msg.content),
-                }],
-            })
-            .collect();
+        match resp_stream.text().await {
+            Ok(payload) => {
+                let gemini_resp: GeminiResponse<'_> =
+                    serde_json::from_str(&payload).map_err(|err| {
+                        tracing::debug!(
+                            %err,
+                            body = %payload,
+                            "Failed to parse response"
+                        );
+                        ChatbotChatError::UnexpectedResponse {
+                            body: payload.clone(),
+                        }
+                    })?;
 
-        let request_body = GeminiRequest {
-            system_instruction: system,
-            contents: gemini_messages,
-        };
+                let usage = gemini_resp.usage_metadata.map(|usage| {
+                    Usage::new(
+                        usage.prompt_token_count,
+                        usage.candidates_token_count,
+                    )
+                });
 
-        let resp_stream = self
-            .client
-            .post(&self.url)
-            .json(&request_body)
-            .send()
-            .await
-            .map_err(|err| {
-                if err.is_timeout() {
-                    ChatbotChatError::Timeout
-                } else {
-                    ChatbotChatError::NetworkError(err)
+                if let Some(reason) = gemini_resp
+                    .prompt_feedback
+                    .and_then(|feedback| feedback.block_reason)
+                {
+                    return Err(ChatbotChatError::Blocked(reason));
                 }
-            })?;
 
-        match resp_stream.text().await {
-            Ok(payload) => {
-                #[expect(
-                    clippy::map_err_ignore,
-                    reason = r#"
-                            Invalid JSON from the API indicates a critical error
-                            so we hide that detail from the end user, as they
-                            cannot address this issue.
+                let candidate =
+                    gemini_resp.candidates.into_iter().next().ok_or_else(
+                        || ChatbotChatError::UnexpectedResponse {
+                            body: payload.clone(),
+                        },
+                    )?;
+
+                let parts = candidate
+                    .content
+                    .map(|content| content.parts)
+                    .filter(|parts| !parts.is_empty());
+                let content =
+                    parts.as_deref().map(|parts| joined_text(parts, false));
+                let reasoning = parts
+                    .as_deref()
+                    .map_or_else(String::new, |parts| joined_text(parts, true));
+                #[cfg(feature = "tools")]
+                let tool_calls: Vec<ToolCall> = parts
+                    .iter()
+                    .flatten()
+                    .filter_map(|part| {
+                        let call = part.function_call.as_ref()?;
+                        Some(ToolCall::new(
+                            call.name.clone(),
+                            call.args.clone(),
+                        ))
+                    })
+                    .collect();
+
+                let content =
+                    match (content, candidate.finish_reason.as_deref()) {
+                        (Some(content), _) => content,
+                        (None, Some(reason)) if is_blocked(reason) => {
+                            return Err(ChatbotChatError::Blocked(
+                                reason.to_owned(),
+                            ));
+                        }
+                        (None, Some(reason)) if reason != "STOP" => {
+                            String::new()
+                        }
+                        (None, _) => {
+                            return Err(ChatbotChatError::UnexpectedResponse {
+                                body: payload.clone(),
+                            });
+                        }
+                    };
+
+                #[cfg_attr(
+                    not(feature = "tools"),
+                    expect(
+                        unused_mut,
+                        reason = r#"
+                            Tool calls are only attached with the `tools`
+                            feature.
                         "#
+                    )
                 )]
-                let gemini_resp: GeminiResponse<'_> =
-                    serde_json::from_str(&payload)
-                        .map_err(|_| ChatbotChatError::UnexpectedResponse)?;
-
-                Ok(gemini_resp
-                    .candidates
-                    .into_iter()
-                    .next()
-                    .and_then(|candidate| {
-                        candidate
-                            .content
-                            .parts
-                            .into_iter()
-                            .next()
-                            .map(|part| Ok(part.text.into_owned()))
-                    })
-                    .unwrap_or_else(|| {
-                        Err(ChatbotChatError::UnexpectedResponse)
-                    })?)
+                let mut response = ChatResponse::new(content, usage)
+                    .with_raw(&payload)
+                    .with_reasoning(Some(reasoning))
+                    .with_finish_reason(candidate.finish_reason);
+                #[cfg(feature = "tools")]
+                {
+                    response.tool_calls = tool_calls;
+                }
+
+                Ok(response)
+            }
+            Err(_) => Err(ChatbotChatError::UnexpectedResponse {
+                body: String::new(),
+            }),
+        }
+    }
+
+    #[inline]
+    async fn send_message_cancellable(
+        &self,
+        messages: &[Message],
+        cancel: &CancellationToken,
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        until_cancelled(cancel, self.send_message(messages)).await
+    }
+
+    #[inline]
+    async fn stream_message(
+        &self,
+        messages: &[Message],
+        on_chunk: &mut (dyn for<'chunk> FnMut(&'chunk str) + Send),
+        cancel: &CancellationToken,
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        let turns = Self::turns(messages);
+        let request_body = self.request_body(messages, &turns);
+        let url = format!(
+            "{}/{}:streamGenerateContent?alt=sse",
+            self.base_url, self.model.id
+        );
+        let response =
+            until_cancelled(cancel, self.post(&url, &request_body)).await?;
+        // Failed requests answer with a plain JSON error and no events.
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = until_cancelled(cancel, async {
+                response
+                    .text()
+                    .await
+                    .map_err(ChatbotChatError::NetworkError)
+            })
+            .await?;
+            tracing::debug!(%status, %body, "Streaming request failed");
+            return Err(ChatbotChatError::UnexpectedResponse { body });
+        }
+        let mut events = response.bytes_stream();
+
+        let mut stream = GeminiStream::default();
+        let mut pending: Vec<u8> = Vec::new();
+        while let Some(bytes) = cancel
+            .run_until_cancelled(events.next())
+            .await
+            .ok_or(ChatbotChatError::Cancelled)?
+        {
+            pending.extend_from_slice(
+                &bytes.map_err(ChatbotChatError::NetworkError)?,
+            );
+            while let Some(end) = pending.iter().position(|&byte| byte == b'\n')
+            {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                stream.push_line(&line, on_chunk)?;
             }
-            Err(_) => Err(ChatbotChatError::UnexpectedResponse),
+        }
+        stream.push_line(&pending, on_chunk)?;
+
+        stream.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    const REPLY: &str = r#"{
+        "candidates": [{
+            "content": {"role": "model", "parts": [{"text": "Hello!"}]},
+            "finishReason": "STOP"
+        }],
+        "usageMetadata": {"promptTokenCount": 3, "candidatesTokenCount": 2}
+    }"#;
+
+    /// Starts a mock server that answers the first generate request with
+    /// `response`.
+    async fn serve(response: ResponseTemplate) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(format!(
+                "/v1beta/models/{DEFAULT_MODEL}:generateContent"
+            )))
+            .and(header("x-goog-api-key", "test-key"))
+            .respond_with(response)
+            .expect(1)
+            .mount(&server)
+            .await;
+        server
+    }
+
+    async fn send_to(
+        server: &MockServer,
+        client: Client,
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        let base_url = format!("{}/v1beta/models", server.uri());
+        let chatbot = GeminiChatbot::with_client(
+            DEFAULT_MODEL,
+            "test-key".to_owned(),
+            &base_url,
+            client,
+        )
+        .unwrap();
+
+        chatbot
+            .send_message(&[Message::new(Role::User, "Hi".to_owned())])
+            .await
+    }
+
+    fn chatbot(model: &str) -> GeminiChatbot {
+        GeminiChatbot::with_client(
+            model,
+            "test-key".to_owned(),
+            GEMINI_BASE_URL,
+            Client::new(),
+        )
+        .unwrap()
+    }
+
+    fn stream(events: &[&str]) -> Result<ChatResponse, ChatbotChatError> {
+        let mut stream = GeminiStream::default();
+        for event in events {
+            stream
+                .push_line(format!("data: {event}").as_bytes(), &mut |_| {})?;
+        }
+        stream.finish()
+    }
+
+    #[tokio::test]
+    async fn reads_a_successful_reply() {
+        let server = serve(
+            ResponseTemplate::new(200).set_body_raw(REPLY, "application/json"),
+        )
+        .await;
+
+        let response = send_to(&server, Client::new()).await.unwrap();
+
+        assert_eq!(response.content, "Hello!");
+        assert_eq!(response.finish_reason.as_deref(), Some("STOP"));
+        let usage = response.usage.unwrap();
+        assert_eq!((usage.prompt, usage.completion), (3, 2));
+    }
+
+    #[tokio::test]
+    async fn slow_replies_time_out() {
+        let server = serve(
+            ResponseTemplate::new(200)
+                .set_body_raw(REPLY, "application/json")
+                .set_delay(Duration::from_secs(2)),
+        )
+        .await;
+        let client = Client::builder()
+            .timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+
+        let result = send_to(&server, client).await;
+
+        assert!(matches!(result, Err(ChatbotChatError::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn too_many_requests_is_rate_limited() {
+        let server =
+            serve(ResponseTemplate::new(429).insert_header("retry-after", "7"))
+                .await;
+
+        let result = send_to(&server, Client::new()).await;
+
+        assert!(matches!(
+            result,
+            Err(ChatbotChatError::RateLimited { retry_after: Some(wait) })
+                if wait == Duration::from_secs(7)
+        ));
+    }
+
+    #[tokio::test]
+    async fn malformed_json_keeps_the_body() {
+        let server = serve(
+            ResponseTemplate::new(200)
+                .set_body_raw("{\"candidates\": [", "application/json"),
+        )
+        .await;
+
+        let result = send_to(&server, Client::new()).await;
+
+        assert!(matches!(
+            result,
+            Err(ChatbotChatError::UnexpectedResponse { ref body })
+                if body == "{\"candidates\": ["
+        ));
+    }
+
+    #[tokio::test]
+    async fn failed_streams_are_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(format!(
+                "/v1beta/models/{DEFAULT_MODEL}:streamGenerateContent"
+            )))
+            .respond_with(ResponseTemplate::new(500).set_body_raw(
+                r#"{"error": {"code": 500, "message": "Internal error"}}"#,
+                "application/json",
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let chatbot = GeminiChatbot::with_client(
+            DEFAULT_MODEL,
+            "test-key".to_owned(),
+            &format!("{}/v1beta/models", server.uri()),
+            Client::new(),
+        )
+        .unwrap();
+
+        let result = chatbot
+            .stream_message(
+                &[Message::new(Role::User, "Hi".to_owned())],
+                &mut |_| {},
+                &CancellationToken::new(),
+            )
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(ChatbotChatError::UnexpectedResponse { ref body })
+                if body.contains("Internal error")
+        ));
+    }
+
+    #[test]
+    fn empty_streams_are_errors() {
+        let result = stream(&[]);
+
+        assert!(matches!(
+            result,
+            Err(ChatbotChatError::UnexpectedResponse { .. })
+        ));
+    }
+
+    #[test]
+    fn assistant_turns_are_sent_as_model() {
+        let chatbot = chatbot(DEFAULT_MODEL);
+        let messages = [
+            Message::new(Role::User, "Hi".to_owned()),
+            Message::new(Role::Assistant, "Hello!".to_owned()),
+            Message::new(Role::User, "How are you?".to_owned()),
+        ];
+        let turns = GeminiChatbot::turns(&messages);
+
+        let body =
+            serde_json::to_value(chatbot.request_body(&messages, &turns))
+                .unwrap();
+        let roles: Vec<&str> = body["contents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|turn| turn["role"].as_str())
+            .collect();
+
+        assert_eq!(roles, ["user", "model", "user"]);
+    }
+
+    #[test]
+    fn joins_every_text_part() {
+        let payload = r#"{
+            "candidates": [{
+                "content": {
+                    "role": "model",
+                    "parts": [
+                        {"text": "Thinking it over.", "thought": true},
+                        {"text": "First part, "},
+                        {"text": "second part."}
+                    ]
+                },
+                "finishReason": "STOP"
+            }]
+        }"#;
+        let response: GeminiResponse<'_> =
+            serde_json::from_str(payload).unwrap();
+        let parts = &response.candidates[0].content.as_ref().unwrap().parts;
+
+        assert_eq!(joined_text(parts, false), "First part, second part.");
+        assert_eq!(joined_text(parts, true), "Thinking it over.");
+    }
+
+    #[test]
+    fn safety_finish_reasons_are_blocks() {
+        let result = stream(&[r#"{"candidates":[{"finishReason":"SAFETY"}]}"#]);
+
+        assert!(
+            matches!(result, Err(ChatbotChatError::Blocked(ref reason)) if reason == "SAFETY"),
+        );
+    }
+
+    #[test]
+    fn max_tokens_is_a_truncated_reply() {
+        let response = stream(&[
+            r#"{"candidates":[{"content":{"role":"model","parts":[{"text":"Partial"}]}}]}"#,
+            r#"{"candidates":[{"finishReason":"MAX_TOKENS"}]}"#,
+        ])
+        .unwrap();
+        assert_eq!(response.content, "Partial");
+        assert!(response.is_truncated());
+
+        let empty =
+            stream(&[r#"{"candidates":[{"finishReason":"MAX_TOKENS"}]}"#])
+                .unwrap();
+        assert!(empty.content.is_empty());
+        assert!(empty.is_truncated());
+    }
+
+    #[test]
+    fn every_model_has_a_display_name() {
+        let mut chatbot = chatbot(DEFAULT_MODEL);
+
+        for id in chatbot.available_models() {
+            chatbot.change_model(id.clone()).unwrap();
+
+            assert_eq!(chatbot.model_id(), id);
+            assert_ne!(chatbot.model(), id, "{id} has no display name");
+            assert_eq!(
+                ModelSpec::find(chatbot.model()).map(|spec| spec.id),
+                Some(id.as_str()),
+            );
         }
     }
 }
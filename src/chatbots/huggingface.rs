@@ -0,0 +1,295 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    chatbots::{
+        build_client, check_status, log_request_body, reject_attachments,
+        until_cancelled,
+    },
+    config::Config,
+    redact_secrets, ChatResponse, Chatbot, ChatbotChatError,
+    ChatbotCreationError, GenerationParams, InvalidModelError, Message,
+    ModelInfo, Role,
+};
+
+const HUGGINGFACE_BASE_URL: &str =
+    "https://api-inference.huggingface.co/models/";
+
+pub const DEFAULT_MODEL: &str = "HuggingFaceH4/zephyr-7b-beta";
+
+const SUGGESTED_MODELS: [&str; 4] = [
+    DEFAULT_MODEL,
+    "mistralai/Mistral-7B-Instruct-v0.3",
+    "meta-llama/Meta-Llama-3-8B-Instruct",
+    "microsoft/Phi-3-mini-4k-instruct",
+];
+
+#[derive(Serialize)]
+struct HuggingFaceParameters {
+    return_full_text: bool,
+}
+
+#[derive(Serialize)]
+struct HuggingFaceRequest {
+    inputs: String,
+    parameters: HuggingFaceParameters,
+}
+
+#[derive(Deserialize)]
+struct HuggingFaceGeneration {
+    generated_text: String,
+}
+
+#[non_exhaustive]
+pub struct HuggingFaceChatbot {
+    api_key: String,
+    model: String,
+    base_url: String,
+    url: String,
+    client: Client,
+    log_requests: bool,
+}
+
+impl HuggingFaceChatbot {
+    /// The model id is appended to `base_url`, as it is to the Inference
+    /// API's `/models` endpoint.
+    #[inline]
+    pub fn with_client(
+        model: String,
+        api_key: String,
+        base_url: &str,
+        client: Client,
+    ) -> Result<Self, ChatbotCreationError> {
+        if model.trim().is_empty() {
+            return Err(ChatbotCreationError::UnknownModel);
+        }
+
+        let base_url = base_url.trim_end_matches('/').to_owned();
+        let url = format!("{base_url}/{model}");
+
+        Ok(Self {
+            api_key,
+            model,
+            base_url,
+            url,
+            client,
+            log_requests: false,
+        })
+    }
+
+    fn render_prompt(messages: &[Message]) -> String {
+        let mut prompt = String::new();
+
+        for msg in messages {
+            prompt.push_str(match msg.role {
+                Role::System => "<|system|>\n",
+                Role::User | Role::Tool => "<|user|>\n",
+                Role::Assistant => "<|assistant|>\n",
+            });
+            prompt.push_str(&msg.content);
+            prompt.push_str("</s>\n");
+        }
+
+        prompt.push_str("<|assistant|>\n");
+        prompt
+    }
+}
+
+#[async_trait]
+impl Chatbot for HuggingFaceChatbot {
+    #[inline]
+    async fn create(
+        model: String,
+        api_key: Option<String>,
+        config: &Config,
+    ) -> Result<Box<dyn Chatbot>, ChatbotCreationError> {
+        let api_key = api_key.ok_or(ChatbotCreationError::ApiKeyMissing)?;
+
+        let mut chatbot = Self::with_client(
+            model,
+            api_key,
+            HUGGINGFACE_BASE_URL,
+            build_client(config)?,
+        )?;
+        chatbot.log_requests = config.log_requests.unwrap_or_default();
+
+        Ok(Box::new(chatbot))
+    }
+
+    #[inline]
+    fn name(&self) -> &'static str {
+        "HuggingFace"
+    }
+
+    #[inline]
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    #[inline]
+    fn model_id(&self) -> &str {
+        &self.model
+    }
+
+    #[inline]
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo::new(None, false, true, false)
+    }
+
+    #[inline]
+    fn available_models(&self) -> Vec<String> {
+        SUGGESTED_MODELS
+            .iter()
+            .map(|&model| model.to_owned())
+            .collect()
+    }
+
+    #[inline]
+    fn change_model(
+        &mut self,
+        new_model: String,
+    ) -> Result<(), InvalidModelError> {
+        if new_model.trim().is_empty() {
+            return Err(InvalidModelError);
+        }
+
+        self.url = format!("{}/{new_model}", self.base_url);
+        self.model = new_model;
+
+        Ok(())
+    }
+
+    #[inline]
+    fn generation_params_mut(&mut self) -> Option<&mut GenerationParams> {
+        None
+    }
+
+    #[inline]
+    async fn send_message(
+        &self,
+        messages: &[Message],
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        reject_attachments(messages, self.name())?;
+
+        let request_body = HuggingFaceRequest {
+            inputs: Self::render_prompt(messages),
+            parameters: HuggingFaceParameters {
+                return_full_text: false,
+            },
+        };
+
+        tracing::debug!(url = %redact_secrets(&self.url), "Sending request");
+        log_request_body(self.log_requests, &request_body);
+
+        let resp_stream = self
+            .client
+            .post(&self.url)
+            .bearer_auth(&self.api_key)
+            .json(&request_body)
+            .send()
+            .await
+            .map_err(|err| {
+                if err.is_timeout() {
+                    ChatbotChatError::Timeout
+                } else {
+                    ChatbotChatError::NetworkError(err)
+                }
+            })?;
+
+        tracing::debug!(status = %resp_stream.status(), "Received response");
+        check_status(&resp_stream, self.name())?;
+
+        match resp_stream.text().await {
+            Ok(payload) => {
+                let generations: Vec<HuggingFaceGeneration> =
+                    serde_json::from_str(&payload).map_err(|err| {
+                        tracing::debug!(
+                            %err,
+                            body = %payload,
+                            "Failed to parse response"
+                        );
+                        ChatbotChatError::UnexpectedResponse {
+                            body: payload.clone(),
+                        }
+                    })?;
+
+                let content = generations
+                    .into_iter()
+                    .next()
+                    .map(|generation| {
+                        generation.generated_text.trim().to_owned()
+                    })
+                    .ok_or_else(|| ChatbotChatError::UnexpectedResponse {
+                        body: payload.clone(),
+                    })?;
+
+                Ok(ChatResponse::new(content, None).with_raw(&payload))
+            }
+            Err(_) => Err(ChatbotChatError::UnexpectedResponse {
+                body: String::new(),
+            }),
+        }
+    }
+
+    #[inline]
+    async fn send_message_cancellable(
+        &self,
+        messages: &[Message],
+        cancel: &CancellationToken,
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        until_cancelled(cancel, self.send_message(messages)).await
+    }
+
+    #[inline]
+    async fn stream_message(
+        &self,
+        messages: &[Message],
+        on_chunk: &mut (dyn for<'chunk> FnMut(&'chunk str) + Send),
+        cancel: &CancellationToken,
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        let response = self.send_message_cancellable(messages, cancel).await?;
+        on_chunk(&response.content);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn sends_to_the_model_under_the_base_url() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(format!("/models/{DEFAULT_MODEL}")))
+            .and(header("authorization", "Bearer test-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"[{"generated_text": " Hello! "}]"#,
+                "application/json",
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let chatbot = HuggingFaceChatbot::with_client(
+            DEFAULT_MODEL.to_owned(),
+            "test-key".to_owned(),
+            &format!("{}/models/", server.uri()),
+            Client::new(),
+        )
+        .unwrap();
+
+        let response = chatbot
+            .send_message(&[Message::new(Role::User, "Hi".to_owned())])
+            .await
+            .unwrap();
+
+        assert_eq!(response.content, "Hello!");
+    }
+}
@@ -0,0 +1,394 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    chatbots::{
+        build_client, check_status, log_request_body, reject_attachments,
+        split_reasoning, until_cancelled,
+    },
+    config::{Config, ProviderConfig},
+    redact_secrets, ChatResponse, Chatbot, ChatbotChatError,
+    ChatbotCreationError, GenerationParams, InvalidModelError, Message,
+    ModelInfo, Role, Usage,
+};
+
+pub const DEFAULT_MODEL: &str = "gpt-4o-mini";
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+
+#[derive(Serialize)]
+struct OpenAiMessage<'content> {
+    role: Role,
+    content: &'content str,
+}
+
+#[derive(Serialize)]
+struct OpenAiRequest<'model, 'messages, 'params> {
+    model: &'model str,
+    messages: Vec<OpenAiMessage<'messages>>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    stop: &'params [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiResponseMessage {
+    /// Null on tool call and refusal turns.
+    content: Option<String>,
+    #[serde(default, alias = "reasoning")]
+    reasoning_content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiChoice {
+    message: OpenAiResponseMessage,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiUsage {
+    #[serde(default)]
+    prompt_tokens: u32,
+    #[serde(default)]
+    completion_tokens: u32,
+}
+
+#[derive(Deserialize)]
+struct OpenAiResponse {
+    choices: Vec<OpenAiChoice>,
+    usage: Option<OpenAiUsage>,
+}
+
+#[non_exhaustive]
+pub struct OpenAiCompatChatbot {
+    name: String,
+    api_key: Option<String>,
+    url: String,
+    models: Vec<String>,
+    model: String,
+    client: Client,
+    params: GenerationParams,
+    log_requests: bool,
+}
+
+impl OpenAiCompatChatbot {
+    #[inline]
+    pub fn from_provider(
+        name: &str,
+        provider: &ProviderConfig,
+        model: Option<String>,
+        api_key: Option<String>,
+        config: &Config,
+    ) -> Result<Box<dyn Chatbot>, ChatbotCreationError> {
+        let models = provider.models.clone().unwrap_or_default();
+
+        let model = model
+            .or_else(|| provider.default_model.clone())
+            .or_else(|| models.first().cloned())
+            .ok_or(ChatbotCreationError::UnknownModel)?;
+
+        if !Self::is_valid_model(&models, &model) {
+            return Err(ChatbotCreationError::UnknownModel);
+        }
+
+        let mut chatbot = Self::with_client(
+            name,
+            model,
+            api_key,
+            &provider.base_url,
+            build_client(config)?,
+        )?;
+        chatbot.models = models;
+        chatbot.log_requests = config.log_requests.unwrap_or_default();
+
+        Ok(Box::new(chatbot))
+    }
+
+    /// Unlike [`Self::from_provider`], there is no model list to check
+    /// against, so any model name is accepted. `base_url` is the API root,
+    /// as in a provider's `base_url`.
+    #[inline]
+    pub fn with_client(
+        name: &str,
+        model: String,
+        api_key: Option<String>,
+        base_url: &str,
+        client: Client,
+    ) -> Result<Self, ChatbotCreationError> {
+        if model.trim().is_empty() {
+            return Err(ChatbotCreationError::UnknownModel);
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            api_key,
+            url: format!("{}/chat/completions", base_url.trim_end_matches('/')),
+            models: Vec::new(),
+            model,
+            client,
+            params: GenerationParams::default(),
+            log_requests: false,
+        })
+    }
+
+    fn is_valid_model(models: &[String], model: &str) -> bool {
+        if models.is_empty() {
+            !model.trim().is_empty()
+        } else {
+            models.iter().any(|known| known == model)
+        }
+    }
+}
+
+#[async_trait]
+impl Chatbot for OpenAiCompatChatbot {
+    #[inline]
+    async fn create(
+        model: String,
+        api_key: Option<String>,
+        config: &Config,
+    ) -> Result<Box<dyn Chatbot>, ChatbotCreationError> {
+        let api_key = api_key.ok_or(ChatbotCreationError::ApiKeyMissing)?;
+
+        let mut chatbot = Self::with_client(
+            "openai",
+            model,
+            Some(api_key),
+            OPENAI_BASE_URL,
+            build_client(config)?,
+        )?;
+        chatbot.log_requests = config.log_requests.unwrap_or_default();
+
+        Ok(Box::new(chatbot))
+    }
+
+    #[inline]
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    #[inline]
+    fn model_id(&self) -> &str {
+        &self.model
+    }
+
+    #[inline]
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo::new(None, false, true, false)
+    }
+
+    #[inline]
+    fn available_models(&self) -> Vec<String> {
+        self.models.clone()
+    }
+
+    #[inline]
+    fn change_model(
+        &mut self,
+        new_model: String,
+    ) -> Result<(), InvalidModelError> {
+        if !Self::is_valid_model(&self.models, &new_model) {
+            return Err(InvalidModelError);
+        }
+
+        self.model = new_model;
+
+        Ok(())
+    }
+
+    #[inline]
+    fn generation_params_mut(&mut self) -> Option<&mut GenerationParams> {
+        Some(&mut self.params)
+    }
+
+    #[inline]
+    async fn send_message(
+        &self,
+        messages: &[Message],
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        reject_attachments(messages, self.name())?;
+
+        let request_body = OpenAiRequest {
+            model: &self.model,
+            messages: messages
+                .iter()
+                .map(|msg| OpenAiMessage {
+                    role: match msg.role {
+                        Role::Tool => Role::User,
+                        Role::System | Role::User | Role::Assistant => msg.role,
+                    },
+                    content: &msg.content,
+                })
+                .collect(),
+            stop: &self.params.stop_sequences,
+            seed: self.params.seed,
+        };
+
+        tracing::debug!(url = %redact_secrets(&self.url), "Sending request");
+        log_request_body(self.log_requests, &request_body);
+
+        let mut request = self.client.post(&self.url).json(&request_body);
+        if let Some(api_key) = self.api_key.as_ref() {
+            request = request.bearer_auth(api_key);
+        }
+
+        let resp_stream = request.send().await.map_err(|err| {
+            if err.is_timeout() {
+                ChatbotChatError::Timeout
+            } else {
+                ChatbotChatError::NetworkError(err)
+            }
+        })?;
+
+        tracing::debug!(status = %resp_stream.status(), "Received response");
+        check_status(&resp_stream, self.name())?;
+
+        match resp_stream.text().await {
+            Ok(payload) => {
+                let openai_resp: OpenAiResponse =
+                    serde_json::from_str(&payload).map_err(|err| {
+                        tracing::debug!(
+                            %err,
+                            body = %payload,
+                            "Failed to parse response"
+                        );
+                        ChatbotChatError::UnexpectedResponse {
+                            body: payload.clone(),
+                        }
+                    })?;
+
+                let usage = openai_resp.usage.map(|usage| {
+                    Usage::new(usage.prompt_tokens, usage.completion_tokens)
+                });
+
+                let choice =
+                    openai_resp.choices.into_iter().next().ok_or_else(
+                        || ChatbotChatError::UnexpectedResponse {
+                            body: payload.clone(),
+                        },
+                    )?;
+                let (think, content) =
+                    split_reasoning(choice.message.content.unwrap_or_default());
+
+                Ok(ChatResponse::new(content, usage)
+                    .with_raw(&payload)
+                    .with_reasoning(choice.message.reasoning_content.or(think))
+                    .with_finish_reason(choice.finish_reason))
+            }
+            Err(_) => Err(ChatbotChatError::UnexpectedResponse {
+                body: String::new(),
+            }),
+        }
+    }
+
+    #[inline]
+    async fn send_message_cancellable(
+        &self,
+        messages: &[Message],
+        cancel: &CancellationToken,
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        until_cancelled(cancel, self.send_message(messages)).await
+    }
+
+    #[inline]
+    async fn stream_message(
+        &self,
+        messages: &[Message],
+        on_chunk: &mut (dyn for<'chunk> FnMut(&'chunk str) + Send),
+        cancel: &CancellationToken,
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        let response = self.send_message_cancellable(messages, cancel).await?;
+        on_chunk(&response.content);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{body_partial_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn splits_reasoning_off_the_reply() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(body_partial_json(serde_json::json!({"model": "local"})))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{
+                    "choices": [{
+                        "message": {
+                            "content": "<think>Greeting.</think>Hello!"
+                        },
+                        "finish_reason": "stop"
+                    }]
+                }"#,
+                "application/json",
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let chatbot = OpenAiCompatChatbot::with_client(
+            "local",
+            "local".to_owned(),
+            None,
+            &format!("{}/v1/", server.uri()),
+            Client::new(),
+        )
+        .unwrap();
+
+        let response = chatbot
+            .send_message(&[Message::new(Role::User, "Hi".to_owned())])
+            .await
+            .unwrap();
+
+        assert_eq!(response.content, "Hello!");
+        assert_eq!(response.reasoning.as_deref(), Some("Greeting."));
+    }
+
+    #[tokio::test]
+    async fn null_content_is_an_empty_reply() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{
+                    "choices": [{
+                        "message": {"role": "assistant", "content": null},
+                        "finish_reason": "tool_calls"
+                    }]
+                }"#,
+                "application/json",
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let chatbot = OpenAiCompatChatbot::with_client(
+            "local",
+            "local".to_owned(),
+            None,
+            &format!("{}/v1", server.uri()),
+            Client::new(),
+        )
+        .unwrap();
+
+        let response = chatbot
+            .send_message(&[Message::new(Role::User, "Hi".to_owned())])
+            .await
+            .unwrap();
+
+        assert!(response.content.is_empty());
+        assert_eq!(response.finish_reason.as_deref(), Some("tool_calls"));
+    }
+}
@@ -0,0 +1,343 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    chatbots::{
+        build_client, check_status, log_request_body, merge_consecutive_roles,
+        reject_attachments, split_reasoning, until_cancelled,
+    },
+    config::Config,
+    ChatResponse, Chatbot, ChatbotChatError, ChatbotCreationError,
+    GenerationParams, InvalidModelError, Message, ModelInfo, Role, Usage,
+};
+
+pub const DEFAULT_MODEL: &str = "sonar";
+
+const PERPLEXITY_BASE_URL: &str = "https://api.perplexity.ai";
+
+const MODELS: [&str; 5] = [
+    DEFAULT_MODEL,
+    "sonar-pro",
+    "sonar-reasoning",
+    "sonar-reasoning-pro",
+    "sonar-deep-research",
+];
+
+const CONTEXT_128K: usize = 128_000;
+
+#[derive(Serialize)]
+struct PerplexityMessage<'content> {
+    role: Role,
+    content: &'content str,
+}
+
+#[derive(Serialize)]
+struct PerplexityRequest<'model, 'messages> {
+    model: &'model str,
+    messages: Vec<PerplexityMessage<'messages>>,
+}
+
+#[derive(Deserialize)]
+struct PerplexityResponseMessage {
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct PerplexityChoice {
+    message: PerplexityResponseMessage,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PerplexityUsage {
+    #[serde(default)]
+    prompt_tokens: u32,
+    #[serde(default)]
+    completion_tokens: u32,
+}
+
+#[derive(Deserialize)]
+struct PerplexityResponse {
+    choices: Vec<PerplexityChoice>,
+    usage: Option<PerplexityUsage>,
+    #[serde(default)]
+    citations: Vec<String>,
+}
+
+#[non_exhaustive]
+pub struct PerplexityChatbot {
+    api_key: String,
+    model: String,
+    url: String,
+    client: Client,
+    log_requests: bool,
+}
+
+impl PerplexityChatbot {
+    /// `base_url` is the API root, replies come from its
+    /// `/chat/completions` endpoint. Only the Sonar models are accepted.
+    #[inline]
+    pub fn with_client(
+        model: String,
+        api_key: String,
+        base_url: &str,
+        client: Client,
+    ) -> Result<Self, ChatbotCreationError> {
+        if !MODELS.contains(&model.as_str()) {
+            return Err(ChatbotCreationError::UnknownModel);
+        }
+
+        Ok(Self {
+            api_key,
+            model,
+            url: format!("{}/chat/completions", base_url.trim_end_matches('/')),
+            client,
+            log_requests: false,
+        })
+    }
+
+    #[inline]
+    pub fn model_ids() -> impl Iterator<Item = &'static str> {
+        MODELS.into_iter()
+    }
+
+    fn with_sources(content: String, citations: &[String]) -> String {
+        if citations.is_empty() {
+            return content;
+        }
+
+        let sources: Vec<String> = citations
+            .iter()
+            .enumerate()
+            .map(|(index, url)| format!("[{}] {url}", index.saturating_add(1)))
+            .collect();
+
+        format!("{content}\n\nSources:\n{}", sources.join("\n"))
+    }
+}
+
+#[async_trait]
+impl Chatbot for PerplexityChatbot {
+    #[inline]
+    async fn create(
+        model: String,
+        api_key: Option<String>,
+        config: &Config,
+    ) -> Result<Box<dyn Chatbot>, ChatbotCreationError> {
+        let api_key = api_key.ok_or(ChatbotCreationError::ApiKeyMissing)?;
+
+        let mut chatbot = Self::with_client(
+            model,
+            api_key,
+            PERPLEXITY_BASE_URL,
+            build_client(config)?,
+        )?;
+        chatbot.log_requests = config.log_requests.unwrap_or_default();
+
+        Ok(Box::new(chatbot))
+    }
+
+    #[inline]
+    fn name(&self) -> &'static str {
+        "Perplexity"
+    }
+
+    #[inline]
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    #[inline]
+    fn model_id(&self) -> &str {
+        &self.model
+    }
+
+    #[inline]
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo::new(Some(CONTEXT_128K), false, true, false)
+    }
+
+    #[inline]
+    fn available_models(&self) -> Vec<String> {
+        Self::model_ids().map(str::to_owned).collect()
+    }
+
+    #[inline]
+    fn change_model(
+        &mut self,
+        new_model: String,
+    ) -> Result<(), InvalidModelError> {
+        if !MODELS.contains(&new_model.as_str()) {
+            return Err(InvalidModelError);
+        }
+
+        self.model = new_model;
+
+        Ok(())
+    }
+
+    #[inline]
+    fn generation_params_mut(&mut self) -> Option<&mut GenerationParams> {
+        None
+    }
+
+    #[inline]
+    async fn send_message(
+        &self,
+        messages: &[Message],
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        reject_attachments(messages, self.name())?;
+
+        // Perplexity rejects anything but strictly alternating turns after
+        // the system prompt.
+        let turns = merge_consecutive_roles(messages);
+        let request_body = PerplexityRequest {
+            model: &self.model,
+            messages: turns
+                .iter()
+                .map(|msg| PerplexityMessage {
+                    role: match msg.role {
+                        Role::Tool => Role::User,
+                        Role::System | Role::User | Role::Assistant => msg.role,
+                    },
+                    content: &msg.content,
+                })
+                .collect(),
+        };
+
+        tracing::debug!(url = %self.url, "Sending request");
+        log_request_body(self.log_requests, &request_body);
+
+        let resp_stream = self
+            .client
+            .post(&self.url)
+            .bearer_auth(&self.api_key)
+            .json(&request_body)
+            .send()
+            .await
+            .map_err(|err| {
+                if err.is_timeout() {
+                    ChatbotChatError::Timeout
+                } else {
+                    ChatbotChatError::NetworkError(err)
+                }
+            })?;
+
+        tracing::debug!(status = %resp_stream.status(), "Received response");
+        check_status(&resp_stream, self.name())?;
+
+        match resp_stream.text().await {
+            Ok(payload) => {
+                let perplexity_resp: PerplexityResponse =
+                    serde_json::from_str(&payload).map_err(|err| {
+                        tracing::debug!(
+                            %err,
+                            body = %payload,
+                            "Failed to parse response"
+                        );
+                        ChatbotChatError::UnexpectedResponse {
+                            body: payload.clone(),
+                        }
+                    })?;
+
+                let usage = perplexity_resp.usage.map(|usage| {
+                    Usage::new(usage.prompt_tokens, usage.completion_tokens)
+                });
+
+                let choice =
+                    perplexity_resp.choices.into_iter().next().ok_or_else(
+                        || ChatbotChatError::UnexpectedResponse {
+                            body: payload.clone(),
+                        },
+                    )?;
+
+                // The sonar-reasoning models put their chain of thought in a
+                // `<think>` block ahead of the answer.
+                let (reasoning, content) =
+                    split_reasoning(choice.message.content);
+
+                Ok(ChatResponse::new(
+                    Self::with_sources(content, &perplexity_resp.citations),
+                    usage,
+                )
+                .with_raw(&payload)
+                .with_reasoning(reasoning)
+                .with_finish_reason(choice.finish_reason))
+            }
+            Err(_) => Err(ChatbotChatError::UnexpectedResponse {
+                body: String::new(),
+            }),
+        }
+    }
+
+    #[inline]
+    async fn send_message_cancellable(
+        &self,
+        messages: &[Message],
+        cancel: &CancellationToken,
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        until_cancelled(cancel, self.send_message(messages)).await
+    }
+
+    #[inline]
+    async fn stream_message(
+        &self,
+        messages: &[Message],
+        on_chunk: &mut (dyn for<'chunk> FnMut(&'chunk str) + Send),
+        cancel: &CancellationToken,
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        let response = self.send_message_cancellable(messages, cancel).await?;
+        on_chunk(&response.content);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn lists_citations_under_the_reply() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .and(header("authorization", "Bearer test-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{
+                    "choices": [{
+                        "message": {"content": "Hello!"},
+                        "finish_reason": "stop"
+                    }],
+                    "citations": ["https://example.com"]
+                }"#,
+                "application/json",
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let chatbot = PerplexityChatbot::with_client(
+            DEFAULT_MODEL.to_owned(),
+            "test-key".to_owned(),
+            &server.uri(),
+            Client::new(),
+        )
+        .unwrap();
+
+        let response = chatbot
+            .send_message(&[Message::new(Role::User, "Hi".to_owned())])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response.content,
+            "Hello!\n\nSources:\n[1] https://example.com"
+        );
+    }
+}
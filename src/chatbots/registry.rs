@@ -0,0 +1,185 @@
+use crate::{
+    chatbots::{
+        dummy::{self, DummyChatbot},
+        gemini::{self, GeminiChatbot},
+        huggingface::{self, HuggingFaceChatbot},
+        openai_compat::{self, OpenAiCompatChatbot},
+        perplexity::{self, PerplexityChatbot},
+    },
+    config::Config,
+    Chatbot, ChatbotCreationError,
+};
+
+const CHATBOTS: [(&str, &str); 5] = [
+    ("gemini", "Google Gemini"),
+    ("huggingface", "HuggingFace Inference API"),
+    ("perplexity", "Perplexity Sonar (web search)"),
+    ("openai", "OpenAI"),
+    ("dummy", "Dummy"),
+];
+
+/// Lists the built-in chatbots and then the `[providers.*]` entries, sorted
+/// by name and described by their base URL, that `enabled_providers` allows.
+#[inline]
+#[must_use]
+pub fn list(config: &Config) -> Vec<(&str, &str)> {
+    let mut providers: Vec<(&str, &str)> = config
+        .providers
+        .iter()
+        .flatten()
+        .map(|(name, provider)| (name.as_str(), provider.base_url.as_str()))
+        .collect();
+    providers.sort_unstable();
+
+    CHATBOTS
+        .into_iter()
+        .chain(providers)
+        .filter(|&(name, _)| config.is_provider_enabled(name))
+        .collect()
+}
+
+#[inline]
+pub async fn create_by_name(
+    name: &str,
+    config: &Config,
+) -> Result<Box<dyn Chatbot>, ChatbotCreationError> {
+    create(name, None, None, config).await
+}
+
+#[inline]
+pub async fn create(
+    name: &str,
+    model: Option<String>,
+    api_key: Option<String>,
+    config: &Config,
+) -> Result<Box<dyn Chatbot>, ChatbotCreationError> {
+    if !config.is_provider_enabled(name) {
+        return Err(ChatbotCreationError::Disabled {
+            name: name.to_owned(),
+        });
+    }
+
+    let api_key = api_key.or_else(|| config.api_key_for(name));
+    let default_models = config.default_models.as_ref();
+
+    match name {
+        "gemini" => {
+            GeminiChatbot::create(
+                model
+                    .or_else(|| default_models?.gemini.clone())
+                    .unwrap_or_else(|| gemini::DEFAULT_MODEL.to_owned()),
+                api_key,
+                config,
+            )
+            .await
+        }
+        "huggingface" => {
+            HuggingFaceChatbot::create(
+                model
+                    .or_else(|| default_models?.huggingface.clone())
+                    .unwrap_or_else(|| huggingface::DEFAULT_MODEL.to_owned()),
+                api_key,
+                config,
+            )
+            .await
+        }
+        "perplexity" => {
+            PerplexityChatbot::create(
+                model
+                    .or_else(|| default_models?.perplexity.clone())
+                    .unwrap_or_else(|| perplexity::DEFAULT_MODEL.to_owned()),
+                api_key,
+                config,
+            )
+            .await
+        }
+        "openai" => {
+            OpenAiCompatChatbot::create(
+                model
+                    .or_else(|| default_models?.openai.clone())
+                    .unwrap_or_else(|| openai_compat::DEFAULT_MODEL.to_owned()),
+                api_key,
+                config,
+            )
+            .await
+        }
+        "dummy" => {
+            DummyChatbot::create(
+                model
+                    .or_else(|| default_models?.dummy.clone())
+                    .unwrap_or_else(|| dummy::DEFAULT_MODEL.to_owned()),
+                None,
+                config,
+            )
+            .await
+        }
+        _ => {
+            let provider = config
+                .provider(name)
+                .ok_or(ChatbotCreationError::UnknownChatbot)?;
+            OpenAiCompatChatbot::from_provider(
+                name, provider, model, api_key, config,
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::config::ProviderConfig;
+
+    fn provider(base_url: &str) -> ProviderConfig {
+        ProviderConfig {
+            base_url: base_url.to_owned(),
+            api_key_env: None,
+            models: None,
+            default_model: Some("local".to_owned()),
+        }
+    }
+
+    #[test]
+    fn lists_configured_providers_after_the_built_ins() {
+        let config = Config {
+            providers: Some(HashMap::from([
+                ("zeta".to_owned(), provider("http://zeta/v1")),
+                ("alpha".to_owned(), provider("http://alpha/v1")),
+            ])),
+            ..Config::default()
+        };
+
+        let names: Vec<&str> =
+            list(&config).into_iter().map(|(name, _)| name).collect();
+
+        assert_eq!(
+            names,
+            [
+                "gemini",
+                "huggingface",
+                "perplexity",
+                "openai",
+                "dummy",
+                "alpha",
+                "zeta"
+            ],
+        );
+        assert!(list(&config).contains(&("alpha", "http://alpha/v1")));
+    }
+
+    #[tokio::test]
+    async fn creates_the_openai_chatbot() {
+        let chatbot = create(
+            "openai",
+            None,
+            Some("test-key".to_owned()),
+            &Config::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(chatbot.name(), "openai");
+        assert_eq!(chatbot.model_id(), openai_compat::DEFAULT_MODEL);
+    }
+}
@@ -1,72 +1,139 @@
-use core::fmt::{self, Display, Formatter};
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{builder::PossibleValuesParser, Parser, Subcommand};
+
+use crate::chatbots::{
+    gemini::{self, GeminiChatbot},
+    huggingface, openai_compat,
+    perplexity::{self, PerplexityChatbot},
+};
+
+const EXIT_CODES: &str = "Exit codes:
+  0  Success
+  1  Any other failure
+  2  Invalid usage, such as an unknown option, chatbot or model
+  3  Configuration error
+  4  Missing or rejected API key
+  5  Network error, timeout or rate limit";
 
 #[non_exhaustive]
 #[derive(Parser)]
-#[command(author, version, about)]
+#[command(author, version, about, after_help = EXIT_CODES)]
 #[command(propagate_version = true)]
+#[expect(
+    clippy::struct_excessive_bools,
+    reason = r#"Each flag maps to an independent command line switch."#
+)]
 pub struct Args {
     #[command(subcommand)]
-    pub command: Option<ChatbotArg>,
-    #[arg(short, long, help = "Set the system prompt")]
+    pub command: Option<CliCommand>,
+    #[arg(short, long, alias = "system", help = "Set the system prompt")]
     pub system_prompt: Option<String>,
     #[arg(long, help = "Disable colored output")]
     pub no_color: bool,
     #[arg(long, help = "Custom config file path", value_name = "FILE")]
     pub config: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "API key for the selected chatbot (overrides config and environment)",
+        value_name = "KEY"
+    )]
+    pub api_key: Option<String>,
     #[arg(
         help = "Input prompt (optional, reads from stdin if `-`, no prompt starts interactive mode, only works if default_chatbot and default_model is set in configuration file)"
     )]
     pub prompt: Option<String>,
     #[arg(long, help = "Disable markdown rendering")]
     pub no_markdown: Option<bool>,
-}
-
-#[non_exhaustive]
-#[derive(Debug, Clone, ValueEnum)]
-pub enum GeminiModel {
-    #[clap(name = "gemini-2.0-flash-exp")]
-    Flash2_0Exp,
-    #[clap(name = "gemini-1.5-flash")]
-    Flash1_5,
-    #[clap(name = "gemini-1.5-flash-8b")]
-    Flash1_5_8B,
-    #[clap(name = "gemini-1.5-pro")]
-    Pro1_5,
-    #[clap(name = "gemini-1.0-pro")]
-    Pro1,
-}
-
-impl Display for GeminiModel {
-    #[inline]
-    #[expect(
-        clippy::min_ident_chars,
-        reason = r#"
-            `f` is the default parameter name for `Display` trait 
-            implementation.
-        "#
+    #[arg(
+        long,
+        alias = "no-history",
+        help = "Don't read or write the history file for this run"
+    )]
+    pub ephemeral: bool,
+    #[arg(
+        long,
+        global = true,
+        help = "Print the reply (or error) as JSON, only used with a prompt or the sessions command"
     )]
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        match *self {
-            Self::Flash2_0Exp => write!(f, "gemini-2.0-flash-exp"),
-            Self::Flash1_5 => write!(f, "gemini-1.5-flash"),
-            Self::Flash1_5_8B => write!(f, "gemini-1.5-flash-8b"),
-            Self::Pro1_5 => write!(f, "gemini-1.5-pro"),
-            Self::Pro1 => write!(f, "gemini-1.0-pro"),
-        }
-    }
+    pub json: bool,
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = "Log requests and responses to stderr (see also RUST_LOG)"
+    )]
+    pub verbose: bool,
 }
 
 #[non_exhaustive]
 #[derive(Subcommand)]
-pub enum ChatbotArg {
+pub enum CliCommand {
     #[command(about = "Chat with the Google Gemini chatbot")]
     Gemini {
-        #[arg(short, long, value_enum, default_value_t = GeminiModel::Flash1_5)]
-        model: GeminiModel,
+        #[arg(
+            short,
+            long,
+            default_value = gemini::DEFAULT_MODEL,
+            value_parser = PossibleValuesParser::new(GeminiChatbot::model_ids())
+        )]
+        model: String,
+    },
+    #[command(about = "Chat with a HuggingFace Inference API model")]
+    Huggingface {
+        #[arg(short, long, default_value = huggingface::DEFAULT_MODEL)]
+        model: String,
+    },
+    #[command(about = "Chat with a Perplexity Sonar model, with web sources")]
+    Perplexity {
+        #[arg(
+            short,
+            long,
+            default_value = perplexity::DEFAULT_MODEL,
+            value_parser = PossibleValuesParser::new(PerplexityChatbot::model_ids())
+        )]
+        model: String,
+    },
+    #[command(about = "Chat with an OpenAI model")]
+    Openai {
+        #[arg(short, long, default_value = openai_compat::DEFAULT_MODEL)]
+        model: String,
     },
     #[command(about = "Chat with the Dummy chatbot")]
     Dummy,
+    #[command(about = "List saved sessions")]
+    Sessions {
+        #[arg(long, help = "Also show each file's size and the total")]
+        size: bool,
+    },
+    #[command(about = "Check that a saved session parses and is well-formed")]
+    Validate {
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+    #[command(about = "Bundle every saved session into one JSON file")]
+    ExportAll {
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
+    #[command(about = "Restore sessions from a file written by export-all")]
+    ImportAll {
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        #[arg(long, help = "Overwrite existing sessions without asking")]
+        force: bool,
+    },
+    #[command(about = "Check the configuration, directories and API keys")]
+    Doctor {
+        #[arg(long, help = "Also send a tiny request to each provider")]
+        ping: bool,
+    },
+    #[command(about = "Print the version, optionally with build details")]
+    Version {
+        #[arg(
+            long,
+            help = "Also show the git commit, build date and rustc version"
+        )]
+        verbose: bool,
+    },
 }
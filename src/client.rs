@@ -0,0 +1,108 @@
+use crate::{
+    chatbots::registry, config::Config, ChatResponse, Chatbot,
+    ChatbotChatError, ChatbotCreationError, Message, Role,
+};
+
+/// Entry point for using llmcli's chatbots from other programs, built with
+/// [`ChatClient::builder`].
+#[non_exhaustive]
+pub struct ChatClient {
+    chatbot: Box<dyn Chatbot>,
+}
+
+#[non_exhaustive]
+pub struct ChatClientBuilder {
+    provider: String,
+    model: Option<String>,
+    api_key: Option<String>,
+}
+
+impl ChatClient {
+    /// Starts building a client for a built-in chatbot or a provider from
+    /// the `[providers]` config section.
+    #[inline]
+    #[must_use]
+    pub fn builder(provider: &str) -> ChatClientBuilder {
+        ChatClientBuilder {
+            provider: provider.to_owned(),
+            model: None,
+            api_key: None,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn chatbot(&self) -> &dyn Chatbot {
+        &*self.chatbot
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn into_chatbot(self) -> Box<dyn Chatbot> {
+        self.chatbot
+    }
+
+    #[inline]
+    pub async fn send(
+        &self,
+        messages: &[Message],
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        self.chatbot.send_message(messages).await
+    }
+
+    /// Runs a single turn: appends `prompt` and the reply to `messages`.
+    ///
+    /// On failure the prompt is removed again so the conversation can be
+    /// retried as is.
+    #[inline]
+    pub async fn ask(
+        &self,
+        messages: &mut Vec<Message>,
+        prompt: &str,
+    ) -> Result<ChatResponse, ChatbotChatError> {
+        messages.push(Message::new(Role::User, prompt.to_owned()));
+
+        match self.send(messages).await {
+            Ok(response) => {
+                messages.push(Message::new(
+                    Role::Assistant,
+                    response.content.clone(),
+                ));
+                Ok(response)
+            }
+            Err(err) => {
+                messages.pop();
+                Err(err)
+            }
+        }
+    }
+}
+
+impl ChatClientBuilder {
+    #[inline]
+    #[must_use]
+    pub fn model(mut self, model: &str) -> Self {
+        self.model = Some(model.to_owned());
+        self
+    }
+
+    /// Overrides the key from the config and environment.
+    #[inline]
+    #[must_use]
+    pub fn api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_owned());
+        self
+    }
+
+    #[inline]
+    pub async fn build(
+        self,
+        config: &Config,
+    ) -> Result<ChatClient, ChatbotCreationError> {
+        let chatbot =
+            registry::create(&self.provider, self.model, self.api_key, config)
+                .await?;
+
+        Ok(ChatClient { chatbot })
+    }
+}
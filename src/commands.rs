@@ -1,22 +1,60 @@
-use std::io;
+use alloc::string::FromUtf8Error;
+use core::num::ParseIntError;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, IsTerminal as _, Read as _},
+    path::Path,
+};
 
+use dialoguer::Select;
+use futures::StreamExt as _;
+use reqwest::{header, StatusCode};
 use thiserror::Error;
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    chatbots::{dummy::DummyChatbot, gemini::GeminiChatbot},
+    chatbots::{self, registry},
     config::Config,
-    session::{Session, SessionError},
-    ui::Printer,
-    Chatbot, ChatbotCreationError, Message, Role,
+    session::{ContextBlob, Session, SessionError},
+    ui::{self, Printer},
+    Attachment, ChatResponse, Chatbot, ChatbotChatError, ChatbotCreationError,
+    GenerationParams, GenerationParamsError, Message, Role,
 };
 
+const DEFAULT_SUMMARIZE_KEEP_TURNS: usize = 2;
+
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+const MAX_CONTEXT_SOURCE_BYTES: u64 = 256 << 10;
+
+/// HTML is mostly markup, so pages may be larger than other sources as long
+/// as their text fits `MAX_CONTEXT_SOURCE_BYTES`.
+const MAX_CONTEXT_PAGE_BYTES: u64 = 4 << 20;
+
+const HTML_TEXT_WIDTH: usize = 100;
+
+/// Attached context may take up at most `1 / MAX_CONTEXT_SHARE` of the
+/// model's context window.
+const MAX_CONTEXT_SHARE: usize = 2;
+
+const SUMMARIZE_PROMPT: &str = "Summarize the conversation so far in a few \
+                                concise paragraphs. Keep every fact, \
+                                decision and open question needed to \
+                                continue it.";
+
 #[non_exhaustive]
 #[derive(Debug, Error)]
 pub enum CommandCreationError {
     #[error("No command specified.")]
     MissingCommand,
-    #[error("Invalid command.")]
-    Invalid,
+    #[error("Unknown command {command}.")]
+    Invalid { command: String },
+    #[error("Unknown command {command}, did you mean {suggestion}?")]
+    Misspelled {
+        command: String,
+        suggestion: &'static str,
+    },
     #[error("System prompt is required.")]
     MissingPrompt,
     #[error("Chatbot name is required.")]
@@ -25,6 +63,40 @@ pub enum CommandCreationError {
     MissingModelName,
     #[error("Filename is required.")]
     MissingFilename,
+    #[error("Template name is required.")]
+    MissingTemplateName,
+    #[error("Prompt is required.")]
+    MissingUserPrompt,
+    #[error("Search query is required.")]
+    MissingQuery,
+    #[error("Seed must be a non-negative integer.")]
+    InvalidSeed(#[source] ParseIntError),
+    #[error("URL is required.")]
+    MissingUrl,
+    #[error(
+        "Unknown context action {action}, expected add, url, list or clear."
+    )]
+    InvalidContextAction { action: String },
+}
+
+#[derive(Debug, Error)]
+enum ContextSourceError {
+    #[error("Failed to read context file: {0}.")]
+    Read(io::Error),
+    #[error("Failed to fetch context URL: {0}.")]
+    Fetch(reqwest::Error),
+    #[error("Context URL returned {0}.")]
+    Status(StatusCode),
+    #[error("Context source is larger than {max}.")]
+    TooLarge { max: String },
+    #[error("Context source is not UTF-8 text.")]
+    NotText(#[source] FromUtf8Error),
+    #[error("Context URL returned unsupported content type {0}.")]
+    UnsupportedType(String),
+    #[error("Failed to extract text from the page: {0}.")]
+    Html(html2text::Error),
+    #[error("{0}")]
+    Client(#[from] ChatbotCreationError),
 }
 
 #[non_exhaustive]
@@ -36,6 +108,10 @@ pub enum CommandExecuteError {
     ChatbotSwitch(#[from] ChatbotCreationError),
     #[error("{0}")]
     Session(#[from] SessionError),
+    #[error("{0}")]
+    Chat(#[from] ChatbotChatError),
+    #[error("Failed to read selection: {0}")]
+    Select(#[from] dialoguer::Error),
     #[error("User quit.")]
     Quit,
 }
@@ -44,8 +120,10 @@ pub struct CommandContext<'parts, 'session, 'chatbot, 'printer, 'config> {
     parts: &'parts [&'parts str],
     session: &'session mut Session,
     chatbot: &'chatbot mut Box<dyn Chatbot>,
-    printer: &'printer Printer,
+    printer: &'printer mut Printer,
     config: &'config Config,
+    follow_up: Option<String>,
+    reloaded_config: Option<Config>,
 }
 
 impl<'parts, 'session, 'chatbot, 'printer, 'config>
@@ -57,7 +135,7 @@ impl<'parts, 'session, 'chatbot, 'printer, 'config>
         parts: &'parts [&'parts str],
         session: &'session mut Session,
         chatbot: &'chatbot mut Box<dyn Chatbot>,
-        printer: &'printer Printer,
+        printer: &'printer mut Printer,
         config: &'config Config,
     ) -> Self {
         Self {
@@ -66,23 +144,478 @@ impl<'parts, 'session, 'chatbot, 'printer, 'config>
             chatbot,
             printer,
             config,
+            follow_up: None,
+            reloaded_config: None,
         }
     }
+
+    #[inline]
+    pub const fn take_follow_up(&mut self) -> Option<String> {
+        self.follow_up.take()
+    }
+
+    #[inline]
+    pub const fn take_reloaded_config(&mut self) -> Option<Config> {
+        self.reloaded_config.take()
+    }
+}
+
+type ParseFn = for<'parts> fn(
+    &'parts [&'parts str],
+    bool,
+) -> Result<Command<'parts>, CommandCreationError>;
+
+#[derive(Clone, Copy)]
+struct CommandSpec {
+    name: &'static str,
+    aliases: &'static [&'static str],
+    args: &'static str,
+    help: &'static str,
+    forceable: bool,
+    parse: ParseFn,
 }
 
+impl CommandSpec {
+    fn matches(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+
+    fn help_line(&self) -> String {
+        let bang = if self.forceable { "[!]" } else { "" };
+        let mut usage = vec![format!("{}{bang}", self.name)];
+        if !self.args.is_empty() {
+            usage.push(self.args.to_owned());
+        }
+        if !self.aliases.is_empty() {
+            let aliases: Vec<String> = self
+                .aliases
+                .iter()
+                .map(|alias| format!("{alias}{bang}"))
+                .collect();
+            usage.push(format!("or {}", aliases.join(", ")));
+        }
+
+        format!("\t{} - {}", usage.join(" "), self.help)
+    }
+}
+
+const COMMANDS: [CommandSpec; 29] = [
+    CommandSpec {
+        name: "/clear",
+        aliases: &["/c"],
+        args: "[all]",
+        help: "Clear the conversation history, all also removes the system prompt",
+        forceable: false,
+        parse: |parts, _| {
+            Ok(Command::Clear {
+                keep_system: parts.get(1) != Some(&"all"),
+            })
+        },
+    },
+    CommandSpec {
+        name: "/system",
+        aliases: &["/sys"],
+        args: "[prompt|@file|clear]",
+        help: "Show, set, load from a file or clear (also /system -) the system prompt",
+        forceable: false,
+        parse: |parts, _| match *parts {
+            [_] => Ok(Command::ShowSystem),
+            [_, "clear" | "-"] => Ok(Command::ClearSystem),
+            [_, path] if path.starts_with('@') => Ok(Command::SystemFromFile {
+                path: path.trim_start_matches('@'),
+            }),
+            [_, ref prompt @ ..] => Ok(Command::System {
+                prompt: prompt.join(" "),
+            }),
+            [] => Err(CommandCreationError::MissingPrompt),
+        },
+    },
+    CommandSpec {
+        name: "/chatbot",
+        aliases: &["/cb"],
+        args: "<chatbot>",
+        help: "Change the chatbot",
+        forceable: false,
+        parse: |parts, _| {
+            parts.get(1).map_or(
+                Err(CommandCreationError::MissingChatbotName),
+                |name| Ok(Command::SwitchChatbot { name }),
+            )
+        },
+    },
+    CommandSpec {
+        name: "/list_chatbots",
+        aliases: &["/lb"],
+        args: "",
+        help: "List all available chatbots",
+        forceable: false,
+        parse: |_, _| Ok(Command::ListChatbots),
+    },
+    CommandSpec {
+        name: "/model",
+        aliases: &["/m"],
+        args: "[model]",
+        help: "Change the chatbot model (no argument opens a picker)",
+        forceable: false,
+        parse: |parts, _| {
+            Ok(Command::SwitchModel {
+                name: parts
+                    .get(1..)
+                    .filter(|name| !name.is_empty())
+                    .map(|name| name.join(" ").trim_matches('"').to_owned()),
+            })
+        },
+    },
+    CommandSpec {
+        name: "/list_models",
+        aliases: &["/lm"],
+        args: "",
+        help: "List all available models for current chatbot",
+        forceable: false,
+        parse: |_, _| Ok(Command::ListModels),
+    },
+    CommandSpec {
+        name: "/info",
+        aliases: &["/i"],
+        args: "",
+        help: "Display current chatbot and model information",
+        forceable: false,
+        parse: |_, _| Ok(Command::Info),
+    },
+    CommandSpec {
+        name: "/save",
+        aliases: &["/s"],
+        args: "[filename]",
+        help: "Save the session, ! overwrites without asking",
+        forceable: true,
+        parse: |parts, force| {
+            Ok(Command::Save {
+                filename: parts.get(1).copied(),
+                force,
+            })
+        },
+    },
+    CommandSpec {
+        name: "/new",
+        aliases: &["/n"],
+        args: "[name]",
+        help: "Start a new session, optionally naming it for /save",
+        forceable: false,
+        parse: |parts, _| {
+            Ok(Command::New {
+                name: parts.get(1).copied(),
+            })
+        },
+    },
+    CommandSpec {
+        name: "/load",
+        aliases: &["/l"],
+        args: "<filename>",
+        help: "Load a saved session",
+        forceable: false,
+        parse: |parts, _| {
+            parts.get(1).map_or(
+                Err(CommandCreationError::MissingFilename),
+                |filename| Ok(Command::Load { filename }),
+            )
+        },
+    },
+    CommandSpec {
+        name: "/delete",
+        aliases: &["/d"],
+        args: "<filename|glob>",
+        help: "Delete a session or every session matching a glob, ! skips the confirmation",
+        forceable: true,
+        parse: |parts, force| {
+            parts.get(1).map_or(
+                Err(CommandCreationError::MissingFilename),
+                |filename| Ok(Command::Delete { filename, force }),
+            )
+        },
+    },
+    CommandSpec {
+        name: "/sessions",
+        aliases: &["/se"],
+        args: "[--size]",
+        help: "List all saved sessions, --size adds their disk usage",
+        forceable: false,
+        parse: |parts, _| {
+            Ok(Command::Sessions {
+                size: parts.get(1) == Some(&"--size"),
+            })
+        },
+    },
+    CommandSpec {
+        name: "/search",
+        aliases: &["/f"],
+        args: "<query>",
+        help: "Find saved sessions mentioning the query",
+        forceable: false,
+        parse: |parts, _| match *parts {
+            [_, ref query @ ..] if !query.is_empty() => Ok(Command::Search {
+                query: query.join(" "),
+            }),
+            _ => Err(CommandCreationError::MissingQuery),
+        },
+    },
+    CommandSpec {
+        name: "/summarize",
+        aliases: &["/sum"],
+        args: "",
+        help: "Replace older messages with a summary",
+        forceable: false,
+        parse: |_, _| Ok(Command::Summarize),
+    },
+    CommandSpec {
+        name: "/regenerate-as",
+        aliases: &["/ra"],
+        args: "<model|chatbot>",
+        help: "Re-answer the last message with another model without changing the current one",
+        forceable: false,
+        parse: |parts, _| {
+            parts
+                .get(1)
+                .map_or(Err(CommandCreationError::MissingModelName), |name| {
+                    Ok(Command::RegenerateAs { name })
+                })
+        },
+    },
+    CommandSpec {
+        name: "/ask-as",
+        aliases: &["/aa"],
+        args: "\"<system prompt>\" <prompt>",
+        help: "Ask a single question with another system prompt without touching the history",
+        forceable: false,
+        parse: |parts, _| parse_ask_as(parts),
+    },
+    CommandSpec {
+        name: "/compare",
+        aliases: &["/cmp"],
+        args: "<chatbot> <chatbot>",
+        help: "Ask two chatbots at once without changing the history",
+        forceable: false,
+        parse: |parts, _| match (parts.get(1), parts.get(2)) {
+            (Some(first), Some(second)) => {
+                Ok(Command::Compare { first, second })
+            }
+            _ => Err(CommandCreationError::MissingChatbotName),
+        },
+    },
+    CommandSpec {
+        name: "/template",
+        aliases: &["/t"],
+        args: "<name> [key=value ...]",
+        help: "Send a prompt from the [templates] config section",
+        forceable: false,
+        parse: |parts, _| match *parts {
+            [_, name, ref args @ ..] => Ok(Command::Template { name, args }),
+            _ => Err(CommandCreationError::MissingTemplateName),
+        },
+    },
+    CommandSpec {
+        name: "/image",
+        aliases: &["/img"],
+        args: "<path>",
+        help: "Attach an image to the next message",
+        forceable: false,
+        parse: |parts, _| {
+            parts
+                .get(1)
+                .map_or(Err(CommandCreationError::MissingFilename), |path| {
+                    Ok(Command::Image { path })
+                })
+        },
+    },
+    CommandSpec {
+        name: "/context",
+        aliases: &["/ctx"],
+        args: "[add <path|url>|url <url>|list|clear]",
+        help: "Attach a file or web page as context for every message, list or clear the attached context",
+        forceable: false,
+        parse: |parts, _| match *parts {
+            [_, "add", source, ..] => Ok(Command::ContextAdd { source }),
+            [_, "add"] => Err(CommandCreationError::MissingFilename),
+            [_, "url", url, ..] => Ok(Command::ContextUrl { url }),
+            [_, "url"] => Err(CommandCreationError::MissingUrl),
+            [] | [_] | [_, "list", ..] => Ok(Command::ContextList),
+            [_, "clear", ..] => Ok(Command::ContextClear),
+            [_, action, ..] => Err(CommandCreationError::InvalidContextAction {
+                action: action.to_owned(),
+            }),
+        },
+    },
+    CommandSpec {
+        name: "/reasoning",
+        aliases: &[],
+        args: "",
+        help: "Toggle showing the chain of thought of reasoning models",
+        forceable: false,
+        parse: |_, _| Ok(Command::ToggleReasoning),
+    },
+    CommandSpec {
+        name: "/stop",
+        aliases: &[],
+        args: "[sequence|clear]",
+        help: "Show, add or clear the stop sequences (\\n for a newline)",
+        forceable: false,
+        parse: |parts, _| match *parts {
+            [_, "clear"] => Ok(Command::ClearStop),
+            [_, ref sequence @ ..] if !sequence.is_empty() => {
+                Ok(Command::Stop {
+                    sequence: sequence.join(" ").replace("\\n", "\n"),
+                })
+            }
+            _ => Ok(Command::ShowStop),
+        },
+    },
+    CommandSpec {
+        name: "/seed",
+        aliases: &[],
+        args: "[n|clear]",
+        help: "Show, set or clear the sampling seed, where the provider supports one",
+        forceable: false,
+        parse: |parts, _| match *parts {
+            [_, "clear", ..] => Ok(Command::Seed { seed: None }),
+            [_, seed, ..] => seed
+                .parse()
+                .map(|seed| Command::Seed { seed: Some(seed) })
+                .map_err(CommandCreationError::InvalidSeed),
+            _ => Ok(Command::ShowSeed),
+        },
+    },
+    CommandSpec {
+        name: "/undo",
+        aliases: &["/u"],
+        args: "",
+        help: "Remove the last exchange from the conversation",
+        forceable: false,
+        parse: |_, _| Ok(Command::Undo),
+    },
+    CommandSpec {
+        name: "/redo",
+        aliases: &[],
+        args: "",
+        help: "Restore the last exchange removed by /undo",
+        forceable: false,
+        parse: |_, _| Ok(Command::Redo),
+    },
+    CommandSpec {
+        name: "/raw",
+        aliases: &[],
+        args: "",
+        help: "Show the last reply's full provider response as JSON",
+        forceable: false,
+        parse: |_, _| Ok(Command::Raw),
+    },
+    CommandSpec {
+        name: "/reload",
+        aliases: &["/rl"],
+        args: "",
+        help: "Reload the config file",
+        forceable: false,
+        parse: |_, _| Ok(Command::Reload),
+    },
+    CommandSpec {
+        name: "/help",
+        aliases: &["/h"],
+        args: "",
+        help: "List all available commands",
+        forceable: false,
+        parse: |_, _| Ok(Command::Help),
+    },
+    CommandSpec {
+        name: "/quit",
+        aliases: &["/q"],
+        args: "",
+        help: "Exit the application",
+        forceable: false,
+        parse: |_, _| Ok(Command::Quit),
+    },
+];
+
 #[non_exhaustive]
 pub enum Command<'parts> {
-    Clear,
-    System { prompt: Message },
-    SwitchChatbot { name: &'parts str },
+    Clear {
+        keep_system: bool,
+    },
+    System {
+        prompt: String,
+    },
+    SystemFromFile {
+        path: &'parts str,
+    },
+    ShowSystem,
+    ClearSystem,
+    SwitchChatbot {
+        name: &'parts str,
+    },
     ListChatbots,
-    SwitchModel { name: &'parts str },
+    SwitchModel {
+        name: Option<String>,
+    },
     ListModels,
     Info,
-    Save { filename: &'parts str },
-    Load { filename: &'parts str },
-    Delete { filename: &'parts str },
-    Sessions,
+    Save {
+        filename: Option<&'parts str>,
+        force: bool,
+    },
+    New {
+        name: Option<&'parts str>,
+    },
+    Load {
+        filename: &'parts str,
+    },
+    Delete {
+        filename: &'parts str,
+        force: bool,
+    },
+    Sessions {
+        size: bool,
+    },
+    Search {
+        query: String,
+    },
+    Summarize,
+    RegenerateAs {
+        name: &'parts str,
+    },
+    AskAs {
+        system: String,
+        prompt: String,
+    },
+    Compare {
+        first: &'parts str,
+        second: &'parts str,
+    },
+    Template {
+        name: &'parts str,
+        args: &'parts [&'parts str],
+    },
+    Image {
+        path: &'parts str,
+    },
+    Stop {
+        sequence: String,
+    },
+    ShowStop,
+    ClearStop,
+    Seed {
+        seed: Option<u64>,
+    },
+    ShowSeed,
+    Undo,
+    Redo,
+    Raw,
+    ToggleReasoning,
+    ContextAdd {
+        source: &'parts str,
+    },
+    ContextUrl {
+        url: &'parts str,
+    },
+    ContextList,
+    ContextClear,
+    Reload,
     Help,
     Quit,
 }
@@ -95,99 +628,96 @@ impl<'parts> Command<'parts> {
         let Some(command_name) = parts.first() else {
             return Err(CommandCreationError::MissingCommand);
         };
+        let command_name = command_name.to_lowercase();
+        let (name, force) = command_name
+            .strip_suffix('!')
+            .map_or((command_name.as_str(), false), |name| (name, true));
 
-        match *command_name {
-            "/clear" | "/c" => Ok(Self::Clear),
-            "/system" | "/sys" => {
-                if parts.len() > 1 {
-                    #[expect(
-                        clippy::indexing_slicing,
-                        reason = r#"
-                            Safe to index: `/system` command requires at
-                            least one argument, ensuring `parts` has
-                            length >= 2
-                        "#
-                    )]
-                    let new_msg =
-                        Message::new(Role::System, parts[1..].join(" "));
-                    Ok(Self::System { prompt: new_msg })
-                } else {
-                    Err(CommandCreationError::MissingPrompt)
-                }
-            }
-            "/chatbot" | "/cb" => parts.get(1).map_or(
-                Err(CommandCreationError::MissingChatbotName),
-                |name| Ok(Self::SwitchChatbot { name }),
-            ),
-            "/list_chatbots" | "/lb" => Ok(Command::ListChatbots),
-            "/model" | "/m" => parts
-                .get(1)
-                .map_or(Err(CommandCreationError::MissingModelName), |name| {
-                    Ok(Self::SwitchModel { name })
-                }),
-            "/list_models" | "/lm" => Ok(Self::ListModels),
-            "/info" | "/i" => Ok(Self::Info),
-            "/save" | "/s" => parts.get(1).map_or(
-                Err(CommandCreationError::MissingFilename),
-                |filename| Ok(Self::Save { filename }),
-            ),
-            "/load" | "/l" => parts.get(1).map_or(
-                Err(CommandCreationError::MissingFilename),
-                |filename| Ok(Self::Load { filename }),
-            ),
-            "/delete" | "/d" => parts.get(1).map_or(
-                Err(CommandCreationError::MissingFilename),
-                |filename| Ok(Self::Delete { filename }),
-            ),
-            "/sessions" | "/se" => Ok(Self::Sessions),
-            "/help" | "/h" => Ok(Self::Help),
-            "/quit" | "/q" => Ok(Self::Quit),
-            _ => Err(CommandCreationError::Invalid),
+        if let Some(spec) = COMMANDS
+            .into_iter()
+            .find(|spec| spec.matches(name) && (spec.forceable || !force))
+        {
+            return (spec.parse)(parts, force);
         }
+
+        Err(match suggest_command(&command_name) {
+            Some(suggestion) => CommandCreationError::Misspelled {
+                command: command_name,
+                suggestion,
+            },
+            None => CommandCreationError::Invalid {
+                command: command_name,
+            },
+        })
     }
 
     #[inline]
-    pub fn execute(
+    pub async fn execute(
         self,
         context: &mut CommandContext<'_, '_, '_, '_, '_>,
     ) -> Result<(), CommandExecuteError> {
         match self {
-            Self::Clear => {
-                context.session.messages.clear();
-                context.printer.print_app_message("Context cleared.")?;
+            Self::Clear { keep_system } => {
+                context.session.conversation.clear(keep_system);
+                context.session.clear_redo();
+                if keep_system {
+                    context.session.conversation.seed_system_prompt(
+                        context.config.default_system_prompt.as_deref(),
+                    );
+                    context
+                        .printer
+                        .print_app_message("Conversation cleared.")?;
+                } else {
+                    context.printer.print_app_message(
+                        "Conversation and system prompt cleared.",
+                    )?;
+                }
             }
             Self::System { prompt } => {
+                context.session.conversation.set_system_prompt(Some(prompt));
+                context.printer.print_app_message("System prompt set.")?;
+            }
+            Self::SystemFromFile { path } => {
+                let prompt = match fs::read_to_string(path) {
+                    Ok(prompt) => prompt,
+                    Err(err) => {
+                        context.printer.print_error_message(&format!(
+                            "Failed to read system prompt from {path}: {err}."
+                        ))?;
+                        return Ok(());
+                    }
+                };
                 context
                     .session
-                    .messages
-                    .retain(|msg| msg.role != Role::System);
-                context.session.messages.insert(0, prompt);
-                context.printer.print_app_message("System prompt set.")?;
+                    .conversation
+                    .set_system_prompt(Some(prompt.trim_end().to_owned()));
+                context.printer.print_app_message(&format!(
+                    "System prompt loaded from {path}."
+                ))?;
             }
-            Self::SwitchChatbot { name } => {
-                let new_chatbot = match name {
-                    "gemini" => GeminiChatbot::create(
-                        context
-                            .config
-                            .default_models
-                            .as_ref()
-                            .and_then(|models| models.gemini.clone())
-                            .ok_or(ChatbotCreationError::UnknownModel)?,
-                        context
-                            .config
-                            .api_keys
-                            .as_ref()
-                            .and_then(|api_keys| api_keys.gemini.clone()),
-                    )?,
-                    "dummy" => DummyChatbot::create("1".to_owned(), None)?,
-                    _ => {
+            Self::ShowSystem => {
+                match context.session.conversation.system_prompt() {
+                    Some(prompt) => {
+                        context.printer.print_app_message(&format!(
+                            "System prompt: {prompt}"
+                        ))?;
+                    }
+                    None => {
                         context
                             .printer
-                            .print_error_message("Invalid chatbot.")?;
-                        return Ok(());
+                            .print_app_message("No system prompt set.")?;
                     }
-                };
-                *context.chatbot = new_chatbot;
+                }
+            }
+            Self::ClearSystem => {
+                context.session.conversation.set_system_prompt(None);
+                context
+                    .printer
+                    .print_app_message("System prompt cleared.")?;
+            }
+            Self::SwitchChatbot { name } => {
+                *context.chatbot =
+                    registry::create_by_name(name, context.config).await?;
                 context.printer.print_app_message(&format!(
                     "Chatbot changed to {}",
                     context.chatbot.name()
@@ -195,13 +725,40 @@ impl<'parts> Command<'parts> {
             }
             Self::ListChatbots => {
                 context.printer.print_app_message("Available chatbots:")?;
-                context
-                    .printer
-                    .print_app_message("\tgemini - Google Gemini")?;
-                context.printer.print_app_message("\tdummy - Dummy")?;
+                for (name, description) in registry::list(context.config) {
+                    context.printer.print_app_message(&format!(
+                        "\t{name} - {description}"
+                    ))?;
+                }
             }
             Self::SwitchModel { name } => {
-                match context.chatbot.change_model(name.to_owned()) {
+                let models = context.chatbot.available_models();
+                let new_model = if let Some(name) = name {
+                    let candidates = find_models(&models, &name);
+                    match *candidates.as_slice() {
+                        [model] => model.to_owned(),
+                        [] => name,
+                        _ => {
+                            context.printer.print_error_message(&format!(
+                                "Ambiguous model name, matches: {}",
+                                candidates.join(", ")
+                            ))?;
+                            return Ok(());
+                        }
+                    }
+                } else if io::stdin().is_terminal() {
+                    let Some(model) = select_model(&models)? else {
+                        return Ok(());
+                    };
+                    model
+                } else {
+                    context.printer.print_error_message(
+                        &CommandCreationError::MissingModelName.to_string(),
+                    )?;
+                    return Ok(());
+                };
+
+                match context.chatbot.change_model(new_model) {
                     Ok(()) => {
                         context.printer.print_app_message(&format!(
                             "Chatbot model changed to {}",
@@ -230,96 +787,325 @@ impl<'parts> Command<'parts> {
                     "Current model: {}",
                     context.chatbot.model()
                 ))?;
-                if let &Some(system_msg) = &context
+                if let Some(context_window) =
+                    context.chatbot.model_info().context_window
+                {
+                    context.printer.print_app_message(&format!(
+                        "Context window: {context_window} tokens"
+                    ))?;
+                }
+                if let Some(prompt) =
+                    context.session.conversation.system_prompt()
+                {
+                    context.printer.print_app_message(&format!(
+                        "System prompt: {prompt}"
+                    ))?;
+                }
+                let messages = &context.session.conversation.messages;
+                let count = |role| {
+                    messages.iter().filter(|msg| msg.role == role).count()
+                };
+                context.printer.print_app_message(&format!(
+                    "Messages: {} ({} user, {} assistant)",
+                    messages.len(),
+                    count(Role::User),
+                    count(Role::Assistant)
+                ))?;
+                if let Some(started) = context
                     .session
+                    .conversation
                     .messages
                     .iter()
-                    .find(|msg| msg.role == Role::System)
+                    .find_map(|msg| msg.timestamp)
                 {
                     context.printer.print_app_message(&format!(
-                        "System prompt: {}",
-                        system_msg.content
+                        "Session started: {}",
+                        started.format("%Y-%m-%d %H:%M:%S UTC")
                     ))?;
                 }
             }
-            Self::Save { filename } => {
-                context.session.save(filename, context.config)?;
+            Self::Save { filename, force } => {
+                let Some(filename) = filename
+                    .map(str::to_owned)
+                    .or_else(|| context.session.current_name.clone())
+                else {
+                    context.printer.print_error_message(
+                        &CommandCreationError::MissingFilename.to_string(),
+                    )?;
+                    return Ok(());
+                };
+
+                let is_current =
+                    context.session.current_name.as_ref() == Some(&filename);
+
+                if !force
+                    && !is_current
+                    && Session::exists(&filename, context.config)?
+                {
+                    if !io::stdin().is_terminal() {
+                        context.printer.print_error_message(&format!(
+                            "Session {filename} already exists, use \
+                             /save! to overwrite it."
+                        ))?;
+                        return Ok(());
+                    }
+
+                    if !context
+                        .printer
+                        .confirm(&format!("Overwrite session {filename}?"))?
+                    {
+                        context.printer.print_app_message("Save cancelled.")?;
+                        return Ok(());
+                    }
+                }
+
+                context.session.provider =
+                    Some(context.chatbot.name().to_lowercase());
+                context.session.model =
+                    Some(context.chatbot.model_id().to_owned());
+                context.session.save(&filename, context.config)?;
                 context.printer.print_app_message(&format!(
-                    "Session saved to {filename}.json"
+                    "Session saved to {filename}"
+                ))?;
+            }
+            Self::New { name } => {
+                *context.session = Session::new();
+                context.session.current_name = name.map(str::to_owned);
+                context.session.conversation.seed_system_prompt(
+                    context.config.default_system_prompt.as_deref(),
+                );
+                context.printer.print_app_message(&name.map_or_else(
+                    || "Started a new session.".to_owned(),
+                    |name| format!("Started a new session named {name}."),
                 ))?;
             }
             Self::Load { filename } => {
                 let loaded_session = Session::load(filename, context.config)?;
+                // Loaded sessions keep the system prompt they were saved with,
+                // or the lack of one.
                 *context.session = loaded_session;
                 context.printer.print_app_message(&format!(
-                    "Session loaded from {filename}.json"
-                ))?;
-            }
-            Self::Delete { filename } => {
-                Session::delete(filename, context.config)?;
-                context.printer.print_app_message(&format!(
-                    "Session {filename}.json deleted."
+                    "Session loaded from {filename}"
                 ))?;
-            }
-            Self::Sessions => {
-                let sessions = Session::list_all(context.config)?;
-                if sessions.is_empty() {
-                    context
-                        .printer
-                        .print_error_message("No saved sessions found.")?;
-                } else {
-                    context.printer.print_app_message("Saved sessions:")?;
-                    for elem in sessions {
+
+                let (Some(provider), Some(model)) = (
+                    context.session.provider.clone(),
+                    context.session.model.clone(),
+                ) else {
+                    return Ok(());
+                };
+
+                if provider == context.chatbot.name().to_lowercase()
+                    && model == context.chatbot.model_id()
+                {
+                    return Ok(());
+                }
+
+                if !io::stdin().is_terminal() {
+                    context.printer.print_app_message(&format!(
+                        "Session was saved with {provider} ({model}), \
+                         continuing with {} ({}).",
+                        context.chatbot.name(),
+                        context.chatbot.model()
+                    ))?;
+                    return Ok(());
+                }
+                if !context.printer.confirm(&format!(
+                    "Session was saved with {provider} ({model}). Switch to it?"
+                ))? {
+                    return Ok(());
+                }
+
+                match registry::create(
+                    &provider,
+                    Some(model),
+                    None,
+                    context.config,
+                )
+                .await
+                {
+                    Ok(chatbot) => {
+                        *context.chatbot = chatbot;
+                        context.printer.print_app_message(&format!(
+                            "Chatbot changed to {} ({})",
+                            context.chatbot.name(),
+                            context.chatbot.model()
+                        ))?;
+                    }
+                    Err(err) => {
                         context
                             .printer
-                            .print_app_message(&format!("\t{elem}"))?;
+                            .print_error_message(&err.to_string())?;
                     }
                 }
             }
+            Self::Delete { filename, force } => {
+                delete_session(context, filename, force)?;
+            }
+            Self::Sessions { size } => list_sessions(context, size)?,
+            Self::Search { query } => {
+                let hits = Session::search(&query, context.config)?;
+                if hits.is_empty() {
+                    context.printer.print_app_message(&format!(
+                        "No sessions mention \"{query}\"."
+                    ))?;
+                    return Ok(());
+                }
+
+                for hit in hits {
+                    let role = match hit.role {
+                        Role::System => "system",
+                        Role::User => "user",
+                        Role::Assistant => "assistant",
+                        Role::Tool => "tool",
+                    };
+                    context.printer.print_app_message(&format!(
+                        "\t{} ({role}): {}",
+                        hit.name, hit.snippet
+                    ))?;
+                }
+            }
+            Self::Image { path } => attach_image(context, path)?,
+            Self::Stop { sequence } => {
+                update_generation_params(context, |params| {
+                    params.add_stop_sequence(sequence)?;
+                    Ok(describe_stop_sequences(params))
+                })?;
+            }
+            Self::ShowStop => update_generation_params(context, |params| {
+                Ok(describe_stop_sequences(params))
+            })?,
+            Self::ClearStop => update_generation_params(context, |params| {
+                params.stop_sequences.clear();
+                Ok(describe_stop_sequences(params))
+            })?,
+            Self::Seed { seed } => {
+                update_generation_params(context, |params| {
+                    params.seed = seed;
+                    Ok(seed.map_or_else(
+                        || "Seed cleared.".to_owned(),
+                        |seed| format!("Seed set to {seed}."),
+                    ))
+                })?;
+            }
+            Self::ShowSeed => update_generation_params(context, |params| {
+                Ok(params.seed.map_or_else(
+                    || "No seed set.".to_owned(),
+                    |seed| format!("Seed: {seed}"),
+                ))
+            })?,
+            Self::Undo => step_history(context, true)?,
+            Self::Redo => step_history(context, false)?,
+            Self::Raw => print_raw_response(context)?,
+            Self::ToggleReasoning => {
+                let message = if context.printer.toggle_reasoning() {
+                    "Reasoning will be shown."
+                } else {
+                    "Reasoning will be hidden."
+                };
+                context.printer.print_app_message(message)?;
+            }
+            Self::ContextAdd { source } => {
+                add_context(context, source, is_url(source)).await?;
+            }
+            Self::ContextUrl { url } => add_context(context, url, true).await?,
+            Self::ContextList => list_context(context)?,
+            Self::ContextClear => {
+                context.session.context.clear();
+                context
+                    .printer
+                    .print_app_message("Context sources removed.")?;
+            }
+            Self::Reload => reload_config(context)?,
             Self::Help => {
                 context.printer.print_app_message("Available commands:")?;
-                context.printer.print_app_message(
-                "\t/clear or /c - Clear the conversation history (including system prompt)",
-            )?;
-                context.printer.print_app_message(
-                "\t/system <prompt> or /sys <prompt> - Set the system prompt",
-            )?;
-                context.printer.print_app_message(
-                "\t/chatbot <chatbot> or /cb <chatbot> - Change the chatbot",
-            )?;
-                context.printer.print_app_message(
-                    "\t/list_chatbots or /lc - List all available chatbots",
-                )?;
-                context.printer.print_app_message(
-                    "\t/model <model> or /m <model> - Change the chatbot model",
-                )?;
-                context.printer.print_app_message(
-                "\t/list_models or /lm - List all available models for current chatbot"
-            )?;
-                context.printer.print_app_message(
-                "\t/info or /i - Display current chatbot and model information",
-            )?;
-                context.printer.print_app_message(
-                    "\t/save <filename> or /s <filename> - Save the session",
-                )?;
-                context.printer.print_app_message(
-                "\t/load <filename> or /l <filename> - Load a saved session",
-            )?;
-                context.printer.print_app_message(
-                    "\t/delete <filename> or /d - Delete a session",
-                )?;
-                context.printer.print_app_message(
-                    "\t/sessions or /se - List all saved session",
-                )?;
-                context.printer.print_app_message(
-                    "\t/delete <filename> or /d - Delete a session",
-                )?;
-                context.printer.print_app_message(
-                    "\t/help or /h - List all available commands",
-                )?;
-                context.printer.print_app_message(
-                    "\t/quit or /q - Exit the application",
-                )?;
+                for spec in COMMANDS {
+                    context.printer.print_app_message(&spec.help_line())?;
+                }
+            }
+            Self::Summarize => {
+                let keep = context
+                    .config
+                    .summarize_keep_turns
+                    .unwrap_or(DEFAULT_SUMMARIZE_KEEP_TURNS)
+                    .saturating_mul(2);
+
+                let (system, history): (Vec<&Message>, Vec<&Message>) =
+                    context.session.conversation.messages.iter().partition(
+                        |msg| msg.role == Role::System && !msg.summary,
+                    );
+
+                let split = history.len().saturating_sub(keep);
+                if split == 0 {
+                    context
+                        .printer
+                        .print_app_message("Nothing to summarize.")?;
+                    return Ok(());
+                }
+
+                let (older, recent) = history.split_at(split);
+
+                let mut request: Vec<Message> = system
+                    .iter()
+                    .chain(older)
+                    .map(|&msg| msg.clone())
+                    .collect();
+                request.push(Message::new(
+                    Role::User,
+                    SUMMARIZE_PROMPT.to_owned(),
+                ));
+
+                let summary = context.chatbot.send_message(&request).await?;
+
+                let mut messages: Vec<Message> =
+                    system.into_iter().cloned().collect();
+                let mut summary = Message::new(
+                    Role::System,
+                    format!(
+                        "Summary of the earlier conversation:\n{}",
+                        summary.content
+                    ),
+                );
+                summary.summary = true;
+                messages.push(summary);
+                messages.extend(recent.iter().map(|&msg| msg.clone()));
+                context.session.conversation.messages = messages;
+
+                context.printer.print_app_message(&format!(
+                    "Summarized {split} messages."
+                ))?;
+            }
+            Self::RegenerateAs { name } => {
+                regenerate_as(context, name).await?;
+            }
+            Self::AskAs { system, prompt } => {
+                ask_as(context, system, prompt).await?;
+            }
+            Self::Compare { first, second } => {
+                compare(context, first, second).await?;
+            }
+            Self::Template { name, args } => {
+                let Some(template) = context
+                    .config
+                    .templates
+                    .as_ref()
+                    .and_then(|templates| templates.get(name))
+                else {
+                    context.printer.print_error_message(&format!(
+                        "Unknown template: {name}."
+                    ))?;
+                    return Ok(());
+                };
+
+                match render_template(template, &parse_template_args(args)) {
+                    Ok(prompt) => context.follow_up = Some(prompt),
+                    Err(placeholder) => {
+                        context.printer.print_error_message(&format!(
+                            "Missing value for {{{placeholder}}}, pass it as \
+                             {placeholder}=<value>."
+                        ))?;
+                    }
+                }
             }
             Self::Quit => {
                 context.printer.print_app_message("Quitting...")?;
@@ -330,3 +1116,762 @@ impl<'parts> Command<'parts> {
         Ok(())
     }
 }
+
+fn parse_template_args<'args>(
+    args: &[&'args str],
+) -> HashMap<&'args str, String> {
+    let mut values: HashMap<&str, String> = HashMap::new();
+    let mut current: Option<&str> = None;
+
+    for &arg in args {
+        if let Some((key, value)) = arg.split_once('=') {
+            values.insert(key, value.to_owned());
+            current = Some(key);
+            continue;
+        }
+
+        if let Some(value) = current.and_then(|key| values.get_mut(key)) {
+            value.push(' ');
+            value.push_str(arg);
+        }
+    }
+
+    values
+}
+
+fn render_template(
+    template: &str,
+    values: &HashMap<&str, String>,
+) -> Result<String, String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut placeholder: Option<String> = None;
+
+    for ch in template.chars() {
+        match (placeholder.as_mut(), ch) {
+            (None, '{') => placeholder = Some(String::new()),
+            (None, _) => rendered.push(ch),
+            (Some(name), '}') => {
+                let value =
+                    values.get(name.as_str()).ok_or_else(|| name.clone())?;
+                rendered.push_str(value);
+                placeholder = None;
+            }
+            (Some(name), _) if ch.is_alphanumeric() || ch == '_' => {
+                name.push(ch);
+            }
+            (Some(name), _) => {
+                rendered.push('{');
+                rendered.push_str(name);
+                rendered.push(ch);
+                placeholder = None;
+            }
+        }
+    }
+
+    if let Some(name) = placeholder {
+        rendered.push('{');
+        rendered.push_str(&name);
+    }
+
+    Ok(rendered)
+}
+
+fn attach_image(
+    context: &mut CommandContext<'_, '_, '_, '_, '_>,
+    path: &str,
+) -> Result<(), CommandExecuteError> {
+    if !context.chatbot.model_info().supports_images {
+        context.printer.print_error_message(&format!(
+            "{} does not support image attachments.",
+            context.chatbot.name()
+        ))?;
+        return Ok(());
+    }
+
+    match Attachment::from_file(Path::new(path)) {
+        Ok(attachment) => {
+            context.session.pending_attachments.push(attachment);
+            context.printer.print_app_message(&format!(
+                "Attached {path} to the next message."
+            ))?;
+        }
+        Err(err) => context.printer.print_error_message(&err.to_string())?,
+    }
+
+    Ok(())
+}
+
+async fn add_context(
+    context: &mut CommandContext<'_, '_, '_, '_, '_>,
+    source: &str,
+    fetch: bool,
+) -> Result<(), CommandExecuteError> {
+    if fetch && !is_url(source) {
+        context
+            .printer
+            .print_error_message("Only http and https URLs are supported.")?;
+        return Ok(());
+    }
+
+    let text = if fetch {
+        fetch_context_url(context.config, source).await
+    } else {
+        read_context_file(source)
+    };
+    let blob = match text {
+        Ok(text) => ContextBlob::new(source.to_owned(), text),
+        Err(err) => {
+            context.printer.print_error_message(&err.to_string())?;
+            return Ok(());
+        }
+    };
+
+    let total = context
+        .session
+        .context_tokens()
+        .saturating_add(blob.estimated_tokens());
+    if let Some(context_window) = context.chatbot.model_info().context_window {
+        if total.saturating_mul(MAX_CONTEXT_SHARE) > context_window {
+            context.printer.print_error_message(&format!(
+                "Context would take ~{total} of the model's {context_window} \
+                 tokens, at most 1/{MAX_CONTEXT_SHARE} is allowed."
+            ))?;
+            return Ok(());
+        }
+    }
+
+    context.printer.print_app_message(&format!(
+        "Added {source} to the context (~{} tokens).",
+        blob.estimated_tokens()
+    ))?;
+    context.session.context.push(blob);
+
+    Ok(())
+}
+
+fn read_context_file(path: &str) -> Result<String, ContextSourceError> {
+    let mut bytes = Vec::new();
+    fs::File::open(path)
+        .and_then(|file| {
+            file.take(MAX_CONTEXT_SOURCE_BYTES.saturating_add(1))
+                .read_to_end(&mut bytes)
+        })
+        .map_err(ContextSourceError::Read)?;
+
+    context_text(bytes)
+}
+
+async fn fetch_context_url(
+    config: &Config,
+    url: &str,
+) -> Result<String, ContextSourceError> {
+    let response = chatbots::build_client(config)?
+        .get(url)
+        .send()
+        .await
+        .map_err(ContextSourceError::Fetch)?;
+    if !response.status().is_success() {
+        return Err(ContextSourceError::Status(response.status()));
+    }
+
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase()
+        });
+    let is_html = matches!(
+        content_type.as_deref(),
+        Some("text/html" | "application/xhtml+xml")
+    );
+    if let Some(kind) = content_type.filter(|kind| {
+        !kind.starts_with("text/")
+            && !kind.ends_with("json")
+            && !kind.ends_with("xml")
+    }) {
+        return Err(ContextSourceError::UnsupportedType(kind));
+    }
+
+    let limit = if is_html {
+        MAX_CONTEXT_PAGE_BYTES
+    } else {
+        MAX_CONTEXT_SOURCE_BYTES
+    };
+    if response.content_length().is_some_and(|len| len > limit) {
+        return Err(too_large(limit));
+    }
+
+    // Chunked replies carry no length, so the limit is checked as they arrive.
+    let mut bytes = Vec::new();
+    let mut chunks = response.bytes_stream();
+    while let Some(chunk) = chunks.next().await {
+        bytes.extend_from_slice(&chunk.map_err(ContextSourceError::Fetch)?);
+        check_context_size(&bytes, limit)?;
+    }
+    if !is_html {
+        return context_text(bytes);
+    }
+
+    let text = html2text::from_read(bytes.as_slice(), HTML_TEXT_WIDTH)
+        .map_err(ContextSourceError::Html)?;
+    check_context_size(text.as_bytes(), MAX_CONTEXT_SOURCE_BYTES)?;
+
+    Ok(text)
+}
+
+fn context_text(bytes: Vec<u8>) -> Result<String, ContextSourceError> {
+    check_context_size(&bytes, MAX_CONTEXT_SOURCE_BYTES)?;
+
+    String::from_utf8(bytes).map_err(ContextSourceError::NotText)
+}
+
+fn check_context_size(
+    bytes: &[u8],
+    limit: u64,
+) -> Result<(), ContextSourceError> {
+    if u64::try_from(bytes.len()).unwrap_or(u64::MAX) > limit {
+        return Err(too_large(limit));
+    }
+
+    Ok(())
+}
+
+fn too_large(limit: u64) -> ContextSourceError {
+    ContextSourceError::TooLarge {
+        max: ui::format_bytes(limit),
+    }
+}
+
+fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+fn list_context(
+    context: &CommandContext<'_, '_, '_, '_, '_>,
+) -> Result<(), CommandExecuteError> {
+    if context.session.context.is_empty() {
+        context.printer.print_app_message("No context attached.")?;
+        return Ok(());
+    }
+
+    context.printer.print_app_message("Context:")?;
+    for blob in &context.session.context {
+        context.printer.print_app_message(&format!(
+            "\t{} (~{} tokens)",
+            blob.source,
+            blob.estimated_tokens()
+        ))?;
+    }
+    context.printer.print_app_message(&format!(
+        "~{} tokens total",
+        context.session.context_tokens()
+    ))?;
+
+    Ok(())
+}
+
+/// Splits `/ask-as "system prompt" prompt`, where an unquoted system prompt is
+/// a single word.
+fn parse_ask_as<'parts>(
+    parts: &[&str],
+) -> Result<Command<'parts>, CommandCreationError> {
+    let text = parts.get(1..).unwrap_or_default().join(" ");
+    let (system, prompt) = text.strip_prefix('"').map_or_else(
+        || text.split_once(' ').unwrap_or((&text, "")),
+        |rest| rest.split_once('"').unwrap_or((rest, "")),
+    );
+    let (system, prompt) = (system.trim(), prompt.trim());
+
+    if system.is_empty() {
+        return Err(CommandCreationError::MissingPrompt);
+    }
+    if prompt.is_empty() {
+        return Err(CommandCreationError::MissingUserPrompt);
+    }
+
+    Ok(Command::AskAs {
+        system: system.to_owned(),
+        prompt: prompt.to_owned(),
+    })
+}
+
+async fn regenerate_as(
+    context: &mut CommandContext<'_, '_, '_, '_, '_>,
+    name: &str,
+) -> Result<(), CommandExecuteError> {
+    let Some(request) = up_to_last_user(&context.session.conversation.messages)
+    else {
+        context
+            .printer
+            .print_error_message("No message to regenerate.")?;
+        return Ok(());
+    };
+    let request = context.session.with_context(request);
+
+    // Provider names come first, "gemini" is also part of every Gemini model
+    // id.
+    let models = context.chatbot.available_models();
+    let candidates = if is_provider(context.config, name) {
+        Vec::new()
+    } else {
+        find_models(&models, name)
+    };
+    let (label, response) = match *candidates.as_slice() {
+        [model] => {
+            let previous = context.chatbot.model_id().to_owned();
+            if let Err(err) = context.chatbot.change_model(model.to_owned()) {
+                context.printer.print_error_message(&err.to_string())?;
+                return Ok(());
+            }
+            let label = format!(
+                "{} ({})",
+                context.chatbot.name(),
+                context.chatbot.model()
+            );
+            let response = send_cancellable(&**context.chatbot, &request).await;
+            if let Err(err) = context.chatbot.change_model(previous) {
+                context.printer.print_error_message(&err.to_string())?;
+            }
+            (label, response?)
+        }
+        [] => {
+            let chatbot =
+                registry::create_by_name(name, context.config).await?;
+            let label = format!("{} ({})", chatbot.name(), chatbot.model());
+            (label, send_cancellable(&*chatbot, &request).await?)
+        }
+        _ => {
+            context.printer.print_error_message(&format!(
+                "Ambiguous model name, matches: {}",
+                candidates.join(", ")
+            ))?;
+            return Ok(());
+        }
+    };
+
+    context.printer.print_chatbot_prefix(&label)?;
+    print_response(context, &response)?;
+
+    Ok(())
+}
+
+async fn compare(
+    context: &CommandContext<'_, '_, '_, '_, '_>,
+    first: &str,
+    second: &str,
+) -> Result<(), CommandExecuteError> {
+    let Some(request) = up_to_last_user(&context.session.conversation.messages)
+    else {
+        context
+            .printer
+            .print_error_message("No message to compare.")?;
+        return Ok(());
+    };
+    let request = context.session.with_context(request);
+
+    let first = registry::create_by_name(first, context.config).await?;
+    let second = registry::create_by_name(second, context.config).await?;
+
+    let (first_result, second_result) = tokio::join!(
+        first.send_message(&request),
+        second.send_message(&request)
+    );
+
+    for (chatbot, result) in [(first, first_result), (second, second_result)] {
+        context.printer.print_chatbot_prefix(&format!(
+            "{} ({})",
+            chatbot.name(),
+            chatbot.model()
+        ))?;
+        match result {
+            Ok(response) => print_response(context, &response)?,
+            Err(err) => {
+                context.printer.print_error_message(&err.to_string())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn ask_as(
+    context: &CommandContext<'_, '_, '_, '_, '_>,
+    system: String,
+    prompt: String,
+) -> Result<(), CommandExecuteError> {
+    let request = [
+        Message::new(Role::System, system),
+        Message::new(Role::User, prompt),
+    ];
+    let request = context.session.with_context(&request);
+    let response = context.chatbot.send_message(&request).await?;
+
+    context.printer.print_chatbot_prefix(&format!(
+        "{} ({})",
+        context.chatbot.name(),
+        context.chatbot.model()
+    ))?;
+    print_response(context, &response)?;
+
+    Ok(())
+}
+
+fn print_response(
+    context: &CommandContext<'_, '_, '_, '_, '_>,
+    response: &ChatResponse,
+) -> io::Result<()> {
+    if let Some(reasoning) = response.reasoning.as_deref() {
+        context.printer.print_reasoning(reasoning)?;
+    }
+
+    context.printer.print_reply(
+        &response.content,
+        context.config.markdown_disabled.unwrap_or_default(),
+    )
+}
+
+fn update_generation_params(
+    context: &mut CommandContext<'_, '_, '_, '_, '_>,
+    update: impl FnOnce(
+        &mut GenerationParams,
+    ) -> Result<String, GenerationParamsError>,
+) -> Result<(), CommandExecuteError> {
+    let name = context.chatbot.name().to_owned();
+    let Some(params) = context.chatbot.generation_params_mut() else {
+        context.printer.print_error_message(&format!(
+            "{name} doesn't support generation parameters."
+        ))?;
+        return Ok(());
+    };
+
+    match update(params) {
+        Ok(message) => context.printer.print_app_message(&message)?,
+        Err(err) => context.printer.print_error_message(&err.to_string())?,
+    }
+
+    Ok(())
+}
+
+fn describe_stop_sequences(params: &GenerationParams) -> String {
+    if params.stop_sequences.is_empty() {
+        return "No stop sequences set.".to_owned();
+    }
+
+    let sequences: Vec<String> = params
+        .stop_sequences
+        .iter()
+        .map(|sequence| format!("\"{}\"", sequence.escape_debug()))
+        .collect();
+    format!("Stop sequences: {}", sequences.join(", "))
+}
+
+fn list_sessions(
+    context: &CommandContext<'_, '_, '_, '_, '_>,
+    size: bool,
+) -> Result<(), CommandExecuteError> {
+    let sessions = Session::list_info(context.config)?;
+    if sessions.is_empty() {
+        context
+            .printer
+            .print_error_message("No saved sessions found.")?;
+        return Ok(());
+    }
+
+    context.printer.print_app_message("Saved sessions:")?;
+    for session in &sessions {
+        let line = if size {
+            format!("\t{} ({})", session.name, ui::format_bytes(session.size))
+        } else {
+            format!("\t{}", session.name)
+        };
+        context.printer.print_app_message(&line)?;
+    }
+
+    if size {
+        let total = sessions.iter().map(|session| session.size).sum();
+        context.printer.print_app_message(&format!(
+            "{} sessions, {} total",
+            sessions.len(),
+            ui::format_bytes(total)
+        ))?;
+    }
+
+    Ok(())
+}
+
+fn delete_session(
+    context: &CommandContext<'_, '_, '_, '_, '_>,
+    filename: &str,
+    force: bool,
+) -> Result<(), CommandExecuteError> {
+    if filename.contains(['*', '?', '[']) {
+        return delete_matching(context, filename, force);
+    }
+
+    if !force && Session::exists(filename, context.config)? {
+        if !io::stdin().is_terminal() {
+            context.printer.print_error_message(&format!(
+                "Refusing to delete session {filename} without confirmation, \
+                 use /delete! to delete it."
+            ))?;
+            return Ok(());
+        }
+
+        if !context
+            .printer
+            .confirm(&format!("Delete session {filename}?"))?
+        {
+            context.printer.print_app_message("Delete cancelled.")?;
+            return Ok(());
+        }
+    }
+
+    Session::delete(filename, context.config)?;
+    context
+        .printer
+        .print_app_message(&format!("Session {filename} deleted."))?;
+
+    Ok(())
+}
+
+fn delete_matching(
+    context: &CommandContext<'_, '_, '_, '_, '_>,
+    pattern: &str,
+    force: bool,
+) -> Result<(), CommandExecuteError> {
+    let names = Session::matching(pattern, context.config)?;
+    if names.is_empty() {
+        context
+            .printer
+            .print_error_message(&format!("No sessions match {pattern}."))?;
+        return Ok(());
+    }
+
+    if !force {
+        if !io::stdin().is_terminal() {
+            context.printer.print_error_message(&format!(
+                "Refusing to delete {} sessions without confirmation, use \
+                 /delete! to delete them.",
+                names.len()
+            ))?;
+            return Ok(());
+        }
+
+        context.printer.print_app_message("Sessions to delete:")?;
+        for name in &names {
+            context.printer.print_app_message(&format!("\t{name}"))?;
+        }
+        if !context
+            .printer
+            .confirm(&format!("Delete these {} sessions?", names.len()))?
+        {
+            context.printer.print_app_message("Delete cancelled.")?;
+            return Ok(());
+        }
+    }
+
+    for name in &names {
+        Session::delete(name, context.config)?;
+    }
+    context
+        .printer
+        .print_app_message(&format!("Deleted {} sessions.", names.len()))?;
+
+    Ok(())
+}
+
+fn print_raw_response(
+    context: &CommandContext<'_, '_, '_, '_, '_>,
+) -> Result<(), CommandExecuteError> {
+    let Some(raw) = context.session.last_response.as_ref() else {
+        context
+            .printer
+            .print_app_message("No response metadata available yet.")?;
+        return Ok(());
+    };
+
+    let json = serde_json::to_string_pretty(raw)
+        .map_err(|err| CommandExecuteError::Print(err.into()))?;
+    context.printer.print_plain(&json)?;
+
+    Ok(())
+}
+
+fn step_history(
+    context: &mut CommandContext<'_, '_, '_, '_, '_>,
+    undo: bool,
+) -> Result<(), CommandExecuteError> {
+    let message = if undo {
+        context.session.undo().map_or_else(
+            || "Nothing to undo.".to_owned(),
+            |count| format!("Removed {count} messages."),
+        )
+    } else {
+        context.session.redo().map_or_else(
+            || "Nothing to redo.".to_owned(),
+            |count| format!("Restored {count} messages."),
+        )
+    };
+
+    context.printer.print_app_message(&message)?;
+
+    Ok(())
+}
+
+fn reload_config(
+    context: &mut CommandContext<'_, '_, '_, '_, '_>,
+) -> Result<(), CommandExecuteError> {
+    let config = match context.config.reload() {
+        Ok(config) => config,
+        Err(err) => {
+            context.printer.print_error_message(&format!(
+                "Failed to reload config: {err}"
+            ))?;
+            return Ok(());
+        }
+    };
+
+    if let Some(default_chatbot) = config.default_chatbot.as_deref() {
+        if config.default_chatbot != context.config.default_chatbot
+            && !default_chatbot.eq_ignore_ascii_case(context.chatbot.name())
+        {
+            context.printer.print_app_message(&format!(
+                "Default chatbot is now {default_chatbot}, use \
+                 /chatbot {default_chatbot} to switch."
+            ))?;
+        }
+    }
+
+    if config.proxy_url != context.config.proxy_url
+        || config.ca_cert_path != context.config.ca_cert_path
+        || config.danger_accept_invalid_certs
+            != context.config.danger_accept_invalid_certs
+        || config.gemini_base_url != context.config.gemini_base_url
+    {
+        context.printer.print_app_message(
+            "Network settings apply to chatbots created from now \
+             on, use /chatbot to recreate the current one.",
+        )?;
+    }
+
+    context.reloaded_config = Some(config);
+    context.printer.print_app_message("Config reloaded.")?;
+
+    Ok(())
+}
+
+fn up_to_last_user(messages: &[Message]) -> Option<&[Message]> {
+    let last = messages.iter().rposition(|msg| msg.role == Role::User)?;
+    messages.get(..=last)
+}
+
+fn suggest_command(command: &str) -> Option<&'static str> {
+    COMMANDS
+        .into_iter()
+        .map(|spec| spec.name)
+        .map(|name| (edit_distance(command, name), name))
+        .filter(|&(distance, _)| distance <= MAX_SUGGESTION_DISTANCE)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, name)| name)
+}
+
+fn edit_distance(lhs: &str, rhs: &str) -> usize {
+    let rhs: Vec<char> = rhs.chars().collect();
+    let mut previous: Vec<usize> = (0..=rhs.len()).collect();
+
+    for (index, lhs_char) in lhs.chars().enumerate() {
+        let mut current = Vec::with_capacity(previous.len());
+        current.push(index.saturating_add(1));
+        for ((&diagonal, &above), &rhs_char) in
+            previous.iter().zip(previous.iter().skip(1)).zip(&rhs)
+        {
+            let left = current.last().copied().unwrap_or_default();
+            current.push(
+                diagonal
+                    .saturating_add(usize::from(lhs_char != rhs_char))
+                    .min(above.saturating_add(1))
+                    .min(left.saturating_add(1)),
+            );
+        }
+        previous = current;
+    }
+
+    previous.last().copied().unwrap_or_default()
+}
+
+fn is_provider(config: &Config, name: &str) -> bool {
+    registry::list(config)
+        .iter()
+        .any(|&(provider, _)| provider == name)
+}
+
+/// Sends `request`, letting Esc or Ctrl+C cancel it like a chat message.
+async fn send_cancellable(
+    chatbot: &dyn Chatbot,
+    request: &[Message],
+) -> Result<ChatResponse, ChatbotChatError> {
+    let cancel = CancellationToken::new();
+    ui::cancel_on_keypress(
+        &cancel,
+        chatbot.send_message_cancellable(request, &cancel),
+    )
+    .await
+}
+
+fn find_models<'model>(
+    models: &'model [String],
+    query: &str,
+) -> Vec<&'model str> {
+    if let Some(model) = models
+        .iter()
+        .find(|model| model.eq_ignore_ascii_case(query))
+    {
+        return vec![model.as_str()];
+    }
+
+    let query = query.to_lowercase();
+    models
+        .iter()
+        .map(String::as_str)
+        .filter(|model| model.to_lowercase().contains(&query))
+        .collect()
+}
+
+fn select_model(
+    models: &[String],
+) -> Result<Option<String>, CommandExecuteError> {
+    let selection = Select::new()
+        .with_prompt("Select a model")
+        .items(models)
+        .default(0)
+        .interact_opt()?;
+
+    Ok(selection.and_then(|index| models.get(index)).cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn help_lines_and_names_are_unique() {
+        let mut lines = HashSet::new();
+        let mut names = HashSet::new();
+
+        for spec in &COMMANDS {
+            let line = spec.help_line();
+            assert!(lines.insert(line.clone()), "duplicate help line: {line}");
+            for name in spec.aliases.iter().chain([&spec.name]) {
+                assert!(names.insert(*name), "{name} is used twice");
+            }
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{Message, Role};
+
+/// The in-memory message list of a chat, without any persistence.
+#[non_exhaustive]
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Conversation {
+    pub messages: Vec<Message>,
+}
+
+impl Conversation {
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            messages: Vec::new(),
+        }
+    }
+
+    /// Appends a timestamped message and returns it for further changes.
+    #[inline]
+    pub fn add_message(&mut self, role: Role, content: String) -> &mut Message {
+        let mut msg = Message::new(role, content);
+        msg.timestamp = Some(Utc::now());
+        self.messages.push(msg);
+
+        #[expect(
+            clippy::unreachable,
+            reason = r#"
+                The message was pushed right above, so the list is not empty.
+            "#
+        )]
+        self.messages.last_mut().unwrap_or_else(|| unreachable!())
+    }
+
+    #[inline]
+    pub fn add_user(&mut self, content: String) -> &mut Message {
+        self.add_message(Role::User, content)
+    }
+
+    #[inline]
+    pub fn add_assistant(&mut self, content: String) -> &mut Message {
+        self.add_message(Role::Assistant, content)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn system_prompt(&self) -> Option<&str> {
+        self.messages
+            .iter()
+            .find(|msg| msg.role == Role::System && !msg.summary)
+            .map(|msg| msg.content.as_str())
+    }
+
+    /// Replaces the system prompt with `prompt`, or removes it on `None`.
+    /// A summary left by `/summarize` is kept.
+    #[inline]
+    pub fn set_system_prompt(&mut self, prompt: Option<String>) {
+        self.messages
+            .retain(|msg| msg.role != Role::System || msg.summary);
+
+        if let Some(prompt) = prompt {
+            self.messages.insert(0, Message::new(Role::System, prompt));
+        }
+    }
+
+    /// Sets the system prompt only if there is none yet.
+    #[inline]
+    pub fn seed_system_prompt(&mut self, prompt: Option<&str>) {
+        let Some(prompt) = prompt else {
+            return;
+        };
+
+        if self.system_prompt().is_none() {
+            self.set_system_prompt(Some(prompt.to_owned()));
+        }
+    }
+
+    #[inline]
+    pub fn clear(&mut self, keep_system: bool) {
+        self.messages.retain(|msg| {
+            keep_system && msg.role == Role::System && !msg.summary
+        });
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn estimated_tokens(&self) -> usize {
+        self.messages.iter().map(Message::estimated_tokens).sum()
+    }
+
+    /// Drops the oldest non-system messages until the estimate fits `limit`,
+    /// always keeping the latest message. Returns how many were dropped.
+    #[inline]
+    pub fn trim_to(&mut self, limit: usize) -> usize {
+        let mut removed: usize = 0;
+
+        while self.estimated_tokens() > limit {
+            let last = self.messages.len().saturating_sub(1);
+            let Some(oldest) = self
+                .messages
+                .iter()
+                .take(last)
+                .position(|msg| msg.role != Role::System)
+            else {
+                break;
+            };
+
+            self.messages.remove(oldest);
+            removed = removed.saturating_add(1);
+        }
+
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summarized() -> Conversation {
+        let mut conversation = Conversation::new();
+        conversation.set_system_prompt(Some("Be brief.".to_owned()));
+        let mut summary = Message::new(
+            Role::System,
+            "Summary of the earlier conversation".to_owned(),
+        );
+        summary.summary = true;
+        conversation.messages.push(summary);
+        conversation.add_user("Go on.".to_owned());
+        conversation
+    }
+
+    #[test]
+    fn changing_the_system_prompt_keeps_the_summary() {
+        let mut conversation = summarized();
+
+        conversation.set_system_prompt(Some("Be thorough.".to_owned()));
+        assert_eq!(conversation.system_prompt(), Some("Be thorough."));
+        assert!(conversation.messages[1].summary);
+
+        conversation.set_system_prompt(None);
+        assert_eq!(conversation.system_prompt(), None);
+        assert!(conversation.messages[0].summary);
+        assert_eq!(conversation.messages.len(), 2);
+    }
+
+    #[test]
+    fn clear_drops_the_summary_with_the_history() {
+        let mut conversation = summarized();
+
+        conversation.clear(true);
+
+        assert_eq!(conversation.messages.len(), 1);
+        assert_eq!(conversation.system_prompt(), Some("Be brief."));
+    }
+}
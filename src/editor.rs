@@ -0,0 +1,125 @@
+use alloc::sync::Arc;
+use std::{
+    env, fs,
+    io::{self, Write as _},
+    process::Command,
+    sync::{Mutex, PoisonError},
+};
+
+use rustyline::{
+    Cmd, ConditionalEventHandler, Event, EventContext, KeyEvent, RepeatCount,
+};
+
+pub const DEFAULT_KEYBINDING: &str = "C-x C-e";
+
+/// Opens the current input line in `$VISUAL` or `$EDITOR` and submits the
+/// saved result.
+///
+/// Rustyline can only accept its own buffer, so the edited text is stashed
+/// here and picked up with [`ExternalEditor::take_edited`] once `readline`
+/// returns. Without an editor the key does nothing and input stays inline.
+#[non_exhaustive]
+#[derive(Clone, Default)]
+pub struct ExternalEditor {
+    edited: Arc<Mutex<Option<String>>>,
+}
+
+impl ExternalEditor {
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn take_edited(&self) -> Option<String> {
+        self.edited
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+}
+
+impl ConditionalEventHandler for ExternalEditor {
+    #[inline]
+    fn handle(
+        &self,
+        _evt: &Event,
+        _n: RepeatCount,
+        _positive: bool,
+        ctx: &EventContext<'_>,
+    ) -> Option<Cmd> {
+        let Some(command) = editor_command() else {
+            return Some(Cmd::Noop);
+        };
+
+        match edit(&command, ctx.line()) {
+            Ok(Some(text)) => {
+                *self.edited.lock().unwrap_or_else(PoisonError::into_inner) =
+                    Some(text);
+                Some(Cmd::AcceptLine)
+            }
+            Ok(None) => Some(Cmd::Noop),
+            Err(err) => {
+                tracing::warn!(%err, editor = %command, "Failed to run editor");
+                // The terminal is in raw mode while rustyline reads a line.
+                eprint!(
+                    "\r\nCould not run editor `{command}`: {err}. Keep typing \
+                     inline.\r\n"
+                );
+                Some(Cmd::Repaint)
+            }
+        }
+    }
+}
+
+/// Parses a key sequence such as `C-x C-e` or `M-e`.
+#[inline]
+#[must_use]
+pub fn parse_keybinding(spec: &str) -> Option<Event> {
+    let keys = spec
+        .split_whitespace()
+        .map(|key| {
+            let mut chars = key.chars();
+            match (chars.next()?, chars.next(), chars.next(), chars.next()) {
+                ('C', Some('-'), Some(ch), None) => {
+                    Some(KeyEvent::ctrl(ch.to_ascii_uppercase()))
+                }
+                ('M', Some('-'), Some(ch), None) => Some(KeyEvent::alt(ch)),
+                (_, _, _, _) => None,
+            }
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    (!keys.is_empty()).then_some(Event::KeySeq(keys))
+}
+
+fn editor_command() -> Option<String> {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .find_map(|var| env::var(var).ok())
+        .filter(|command| !command.trim().is_empty())
+}
+
+fn edit(command: &str, initial: &str) -> io::Result<Option<String>> {
+    // A fresh private file, removed again when `draft` is dropped.
+    let mut draft = tempfile::Builder::new()
+        .prefix("llmcli-prompt-")
+        .suffix(".md")
+        .tempfile()?;
+    draft.write_all(initial.as_bytes())?;
+    draft.flush()?;
+
+    let mut args = command.split_whitespace();
+    let program = args.next().unwrap_or_default();
+    let status = Command::new(program).args(args).arg(draft.path()).status();
+    let edited = fs::read_to_string(draft.path());
+
+    if !status?.success() {
+        return Ok(None);
+    }
+
+    let edited = edited?.trim_end().to_owned();
+    Ok((!edited.is_empty()).then_some(edited))
+}
@@ -1,9 +1,13 @@
 use alloc::borrow::Cow;
-use std::{fs::File, io, path::PathBuf};
+use std::{
+    fs::{self, File},
+    io,
+    path::PathBuf,
+};
 
 use thiserror::Error;
 
-use crate::config::Config;
+use crate::{config::Config, paths};
 
 #[non_exhaustive]
 #[derive(Debug, Error)]
@@ -20,13 +24,13 @@ pub fn locate_file(config: &Config) -> Result<Cow<'_, PathBuf>, HistoryError> {
         return Ok(Cow::Borrowed(path));
     }
 
-    if let Some(mut path) = dirs::cache_dir() {
-        path.push("llmcli_history.txt");
-        if !path.exists() {
-            File::create(&path)?;
+    let path = paths::history_file();
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
         }
-        Ok(Cow::Owned(path))
-    } else {
-        Err(HistoryError::NoCacheDir)
+        File::create(&path)?;
     }
+
+    Ok(Cow::Owned(path))
 }
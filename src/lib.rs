@@ -1,65 +1,421 @@
 extern crate alloc;
 
-use alloc::boxed::Box;
-use std::env::VarError;
+use alloc::{borrow::Cow, boxed::Box};
+use core::time::Duration;
+use std::{fs, io, path::Path};
 
 use async_trait::async_trait;
-use serde::{Deserialize, Serialize};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::{DateTime, Utc};
+use config::Config;
+use serde::{de, Deserialize, Deserializer, Serialize};
 use thiserror::Error;
+use tokio_util::sync::CancellationToken;
 
 pub mod chatbots;
 pub mod cli;
+pub mod client;
 pub mod commands;
 pub mod config;
+pub mod conversation;
+pub mod editor;
 pub mod history;
+pub mod paths;
 pub mod session;
 pub mod ui;
 
 #[non_exhaustive]
-#[derive(Debug, PartialEq, Eq, Deserialize, Serialize, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
 pub enum Role {
     System,
     User,
-    #[serde(alias = "model")]
     Assistant,
+    Tool,
+}
+
+const ROLE_NAMES: [&str; 5] = ["system", "user", "assistant", "model", "tool"];
+
+/// Ignores case, so hand-edited and older session files with roles like
+/// `"User"` still load. Gemini's `"model"` is read as [`Role::Assistant`].
+#[expect(
+    clippy::missing_trait_methods,
+    reason = r#"
+        The default `deserialize_in_place` is fine for a fieldless enum.
+    "#
+)]
+impl<'de> Deserialize<'de> for Role {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = Cow::<str>::deserialize(deserializer)?;
+
+        match name.to_ascii_lowercase().as_str() {
+            "system" => Ok(Self::System),
+            "user" => Ok(Self::User),
+            "assistant" | "model" => Ok(Self::Assistant),
+            "tool" => Ok(Self::Tool),
+            _ => Err(de::Error::unknown_variant(&name, &ROLE_NAMES)),
+        }
+    }
 }
 
 #[non_exhaustive]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Message {
     pub role: Role,
     pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<ToolPayload>,
+    /// Marks the system message `/summarize` puts in place of older turns,
+    /// which is part of the history rather than the system prompt.
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    pub summary: bool,
 }
 
 impl Message {
     #[inline]
     #[must_use]
     pub const fn new(role: Role, content: String) -> Self {
-        Self { role, content }
+        Self {
+            role,
+            content,
+            timestamp: None,
+            attachments: Vec::new(),
+            tool: None,
+            summary: false,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn estimated_tokens(&self) -> usize {
+        self.content.chars().count().div_ceil(4)
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ModelInfo {
+    pub context_window: Option<usize>,
+    pub supports_streaming: bool,
+    pub supports_system_prompt: bool,
+    pub supports_images: bool,
+}
+
+impl ModelInfo {
+    #[inline]
+    #[must_use]
+    pub const fn new(
+        context_window: Option<usize>,
+        supports_streaming: bool,
+        supports_system_prompt: bool,
+        supports_images: bool,
+    ) -> Self {
+        Self {
+            context_window,
+            supports_streaming,
+            supports_system_prompt,
+            supports_images,
+        }
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Default)]
+pub struct GenerationParams {
+    pub stop_sequences: Vec<String>,
+    pub seed: Option<u64>,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum GenerationParamsError {
+    #[error("Stop sequence can't be empty.")]
+    EmptyStopSequence,
+    #[error("Too many stop sequences, at most {max} are allowed.")]
+    TooManyStopSequences { max: usize },
+}
+
+impl GenerationParams {
+    pub const MAX_STOP_SEQUENCES: usize = 5;
+
+    #[inline]
+    pub fn add_stop_sequence(
+        &mut self,
+        sequence: String,
+    ) -> Result<(), GenerationParamsError> {
+        if sequence.is_empty() {
+            return Err(GenerationParamsError::EmptyStopSequence);
+        }
+
+        if self.stop_sequences.len() >= Self::MAX_STOP_SEQUENCES {
+            return Err(GenerationParamsError::TooManyStopSequences {
+                max: Self::MAX_STOP_SEQUENCES,
+            });
+        }
+
+        self.stop_sequences.push(sequence);
+
+        Ok(())
+    }
+}
+
+/// A function the model asked to call, carried by an assistant message.
+#[non_exhaustive]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ToolCall {
+    pub name: String,
+    pub arguments: serde_json::Value,
+}
+
+impl ToolCall {
+    #[inline]
+    #[must_use]
+    pub const fn new(name: String, arguments: serde_json::Value) -> Self {
+        Self { name, arguments }
+    }
+}
+
+/// The output of a [`ToolCall`], carried by a [`Role::Tool`] message.
+#[non_exhaustive]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ToolResult {
+    pub name: String,
+    pub output: serde_json::Value,
+}
+
+impl ToolResult {
+    #[inline]
+    #[must_use]
+    pub const fn new(name: String, output: serde_json::Value) -> Self {
+        Self { name, output }
     }
 }
 
+#[non_exhaustive]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ToolPayload {
+    Call(ToolCall),
+    Result(ToolResult),
+}
+
+/// An image sent inline with a message, stored base64-encoded.
+#[non_exhaustive]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    pub mime_type: String,
+    pub data: String,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum AttachmentError {
+    #[error("Failed to read attachment: {0}.")]
+    Read(#[from] io::Error),
+    #[error("Unsupported image type, use png, jpeg, webp, heic or heif.")]
+    UnsupportedType,
+}
+
+impl Attachment {
+    #[inline]
+    pub fn from_file(path: &Path) -> Result<Self, AttachmentError> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        let mime_type = match extension.as_deref() {
+            Some("png") => "image/png",
+            Some("jpg" | "jpeg") => "image/jpeg",
+            Some("webp") => "image/webp",
+            Some("heic") => "image/heic",
+            Some("heif") => "image/heif",
+            _ => return Err(AttachmentError::UnsupportedType),
+        };
+
+        Ok(Self {
+            mime_type: mime_type.to_owned(),
+            data: STANDARD.encode(fs::read(path)?),
+        })
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt: u32,
+    pub completion: u32,
+}
+
+impl Usage {
+    #[inline]
+    #[must_use]
+    pub const fn new(prompt: u32, completion: u32) -> Self {
+        Self { prompt, completion }
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct ChatResponse {
+    pub content: String,
+    pub usage: Option<Usage>,
+    pub tool_calls: Vec<ToolCall>,
+    pub raw: Option<serde_json::Value>,
+    pub reasoning: Option<String>,
+    pub finish_reason: Option<String>,
+}
+
+impl ChatResponse {
+    #[inline]
+    #[must_use]
+    pub const fn new(content: String, usage: Option<Usage>) -> Self {
+        Self {
+            content,
+            usage,
+            tool_calls: Vec::new(),
+            raw: None,
+            reasoning: None,
+            finish_reason: None,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn with_finish_reason(mut self, finish_reason: Option<String>) -> Self {
+        self.finish_reason = finish_reason;
+        self
+    }
+
+    /// Whether the provider stopped because it ran out of output tokens.
+    #[inline]
+    #[must_use]
+    pub fn is_truncated(&self) -> bool {
+        matches!(self.finish_reason.as_deref(), Some("length" | "MAX_TOKENS"))
+    }
+
+    /// Attaches the model's separate chain of thought, if it sent one.
+    #[inline]
+    #[must_use]
+    pub fn with_reasoning(mut self, reasoning: Option<String>) -> Self {
+        self.reasoning = reasoning.filter(|text| !text.trim().is_empty());
+        self
+    }
+
+    /// Keeps the provider's full response body, finish reasons, safety
+    /// ratings and citations included, for `/raw`.
+    #[inline]
+    #[must_use]
+    pub fn with_raw(mut self, payload: &str) -> Self {
+        self.raw = serde_json::from_str(payload).ok();
+        self
+    }
+}
+
+const SECRET_PARAMS: [&str; 5] =
+    ["key", "api_key", "apikey", "token", "access_token"];
+const SECRET_PREFIXES: [&str; 5] = ["AIza", "sk-", "hf_", "gsk_", "xai-"];
+const MIN_SECRET_LEN: usize = 16;
+/// Characters that join the segments of a single token, as in JWTs and
+/// base64.
+const SECRET_JOINERS: [char; 4] = ['.', '+', '/', '~'];
+
+/// Replaces anything that looks like an API key with `[REDACTED]`.
+///
+/// Catches values of key-like query parameters and `Bearer` tokens as well as
+/// tokens carrying a well-known provider prefix, such as Google's `AIza`.
+#[inline]
+#[must_use]
+pub fn redact_secrets(text: &str) -> String {
+    let is_token_char =
+        |ch: char| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_';
+    let mut redacted = String::with_capacity(text.len());
+    let mut redact_next = false;
+
+    for piece in text.split_inclusive(|ch: char| !is_token_char(ch)) {
+        let token = piece.trim_end_matches(|ch: char| !is_token_char(ch));
+        let separator = piece.strip_prefix(token).unwrap_or_default();
+        let looks_like_key = token.len() >= MIN_SECRET_LEN
+            && SECRET_PREFIXES
+                .iter()
+                .any(|prefix| token.starts_with(prefix));
+
+        if !token.is_empty() && (redact_next || looks_like_key) {
+            redacted.push_str("[REDACTED]");
+        } else {
+            redacted.push_str(token);
+        }
+        redacted.push_str(separator);
+
+        let continues_secret = redact_next
+            && !separator.is_empty()
+            && separator.chars().all(|ch| SECRET_JOINERS.contains(&ch));
+        redact_next = continues_secret
+            || (separator == "="
+                && SECRET_PARAMS
+                    .iter()
+                    .any(|param| token.eq_ignore_ascii_case(param)))
+            || (separator == " " && token.eq_ignore_ascii_case("bearer"));
+    }
+
+    redacted
+}
+
 #[non_exhaustive]
 #[derive(Debug, Error)]
 pub enum ChatbotChatError {
     #[error("Timeout.")]
     Timeout,
-    #[error("Network error: {0}.")]
+    #[error("Network error: {}.", redact_secrets(&.0.to_string()))]
     NetworkError(#[from] reqwest::Error),
     #[error("Unexpected response.")]
-    UnexpectedResponse,
+    UnexpectedResponse { body: String },
+    #[error("Response blocked: {0}.")]
+    Blocked(String),
+    #[error("Rate limited, try again {}.", retry_hint(*.retry_after))]
+    RateLimited { retry_after: Option<Duration> },
+    #[error("Invalid or missing API key for {provider}.")]
+    Unauthorized { provider: String },
+    #[error("{provider} does not support image attachments.")]
+    AttachmentsUnsupported { provider: String },
+    #[error("Request cancelled.")]
+    Cancelled,
+}
+
+fn retry_hint(retry_after: Option<Duration>) -> String {
+    retry_after.map_or_else(
+        || "later".to_owned(),
+        |retry_after| format!("in {}s", retry_after.as_secs()),
+    )
 }
 
 #[non_exhaustive]
 #[derive(Debug, Error)]
 pub enum ChatbotCreationError {
     #[error("API key missing.")]
-    ApiKeyMissing(#[from] VarError),
+    ApiKeyMissing,
     #[error("Unknown chatbot.")]
     UnknownChatbot,
+    #[error("Chatbot {name} is disabled in the configuration.")]
+    Disabled { name: String },
     #[error("Unknown model.")]
     UnknownModel,
+    #[error("Invalid proxy URL, expected `http://[user:pass@]host:port`.")]
+    InvalidProxy(#[source] reqwest::Error),
+    #[error("Failed to read CA certificate: {0}.")]
+    CaCert(io::Error),
+    #[error("Invalid CA certificate, expected a PEM file: {0}.")]
+    InvalidCaCert(reqwest::Error),
+    #[error("Failed to build HTTP client: {0}.")]
+    HttpClient(reqwest::Error),
+    #[error("Failed to read the dummy script: {0}.")]
+    DummyScript(io::Error),
 }
 
 #[non_exhaustive]
@@ -69,26 +425,108 @@ pub struct InvalidModelError;
 
 #[async_trait]
 pub trait Chatbot {
-    fn create(
+    async fn create(
         model: String,
         api_key: Option<String>,
+        config: &Config,
     ) -> Result<Box<dyn Chatbot>, ChatbotCreationError>
     where
         Self: Sized;
 
-    fn name(&self) -> &'static str;
+    fn name(&self) -> &str;
+
+    fn model(&self) -> &str;
+
+    fn model_id(&self) -> &str;
 
-    fn model(&self) -> &'static str;
+    fn model_info(&self) -> ModelInfo;
 
-    fn available_models(&self) -> &[&str];
+    fn available_models(&self) -> Vec<String>;
 
     fn change_model(
         &mut self,
         new_model: String,
     ) -> Result<(), InvalidModelError>;
 
+    /// Returns `None` for chatbots that don't accept generation parameters.
+    fn generation_params_mut(&mut self) -> Option<&mut GenerationParams>;
+
     async fn send_message(
         &self,
         messages: &[Message],
-    ) -> Result<String, ChatbotChatError>;
+    ) -> Result<ChatResponse, ChatbotChatError>;
+
+    /// Like [`Chatbot::send_message`], but gives up with
+    /// [`ChatbotChatError::Cancelled`] and drops the connection as soon as
+    /// `cancel` is cancelled.
+    async fn send_message_cancellable(
+        &self,
+        messages: &[Message],
+        cancel: &CancellationToken,
+    ) -> Result<ChatResponse, ChatbotChatError>;
+
+    /// Like [`Chatbot::send_message_cancellable`], but passes the reply to
+    /// `on_chunk` as it arrives. Chatbots that can't stream pass the whole
+    /// reply at once.
+    async fn stream_message(
+        &self,
+        messages: &[Message],
+        on_chunk: &mut (dyn for<'chunk> FnMut(&'chunk str) + Send),
+        cancel: &CancellationToken,
+    ) -> Result<ChatResponse, ChatbotChatError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOOGLE_KEY: &str = "AIzaSyA1b2C3d4E5f6G7h8I9j0KlMnOpQrStUv";
+
+    #[test]
+    fn roles_deserialize_ignoring_case() {
+        for (name, role) in [
+            ("\"USER\"", Role::User),
+            ("\"Assistant\"", Role::Assistant),
+            ("\"model\"", Role::Assistant),
+        ] {
+            assert_eq!(serde_json::from_str::<Role>(name).unwrap(), role);
+        }
+    }
+
+    #[test]
+    fn unknown_roles_are_rejected() {
+        let err = serde_json::from_str::<Role>("\"robot\"").unwrap_err();
+
+        assert!(err.to_string().contains("unknown variant `robot`"), "{err}");
+    }
+
+    #[test]
+    fn redacts_key_query_parameters() {
+        let url = "https://example.com/v1/models?alt=sse&key=secret123&x=1";
+
+        assert_eq!(
+            redact_secrets(url),
+            "https://example.com/v1/models?alt=sse&key=[REDACTED]&x=1",
+        );
+    }
+
+    #[test]
+    fn redacts_bearer_tokens() {
+        assert_eq!(
+            redact_secrets("Authorization: Bearer abc.def-123"),
+            "Authorization: Bearer [REDACTED].[REDACTED]",
+        );
+    }
+
+    #[tokio::test]
+    async fn network_errors_hide_the_key_in_the_url() {
+        let url = format!("http://127.0.0.1:1/v1beta/models?key={GOOGLE_KEY}");
+        let err = reqwest::Client::new().get(url).send().await.unwrap_err();
+        assert!(err.to_string().contains(GOOGLE_KEY));
+
+        let message = ChatbotChatError::NetworkError(err).to_string();
+
+        assert!(!message.contains(GOOGLE_KEY), "{message}");
+        assert!(message.contains("key=[REDACTED]"), "{message}");
+    }
 }
@@ -1,106 +1,467 @@
 use std::{
+    env, fs,
     io::{self, IsTerminal as _, Read as _},
+    path::{Path, PathBuf},
     process,
 };
 
+use chrono::{DateTime, Utc};
 use clap::Parser as _;
 use llmcli::{
-    chatbots::{dummy::DummyChatbot, gemini::GeminiChatbot},
-    cli::{Args, ChatbotArg},
+    chatbots::registry,
+    cli::{Args, CliCommand},
+    client::ChatClient,
     commands::{Command, CommandContext, CommandExecuteError},
     config::Config,
+    editor::{self, ExternalEditor},
     history::{self, HistoryError},
-    session::Session,
-    ui::Printer,
-    Chatbot, ChatbotChatError, ChatbotCreationError, Role,
+    redact_secrets,
+    session::{Session, SessionError},
+    ui::{self, Printer, StreamBuffer},
+    ChatResponse, Chatbot, ChatbotChatError, ChatbotCreationError, Message,
+    Role, Usage,
 };
-use rustyline::{error::ReadlineError, DefaultEditor};
+use rustyline::{error::ReadlineError, DefaultEditor, EventHandler};
+use serde::Serialize;
 use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+use tracing_subscriber::EnvFilter;
+
+const PARTIAL_REPLY_MARKER: &str = "[Reply interrupted]";
+
+// Exit codes, listed in `llmcli --help`. Invalid usage matches the code clap
+// exits with on bad arguments.
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_CONFIG: i32 = 3;
+const EXIT_AUTH: i32 = 4;
+const EXIT_NETWORK: i32 = 5;
 
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
-    let printer = Printer::new(args.no_color);
+    init_logging(args.verbose);
+    let list_sessions =
+        matches!(args.command, Some(CliCommand::Sessions { .. }));
+    let json = args.json && (args.prompt.is_some() || list_sessions);
+    if let Some(CliCommand::Doctor { ping }) = args.command {
+        let healthy = run_doctor(args.config, ping).await;
+        process::exit(i32::from(!healthy));
+    }
+    if let Some(CliCommand::Version { verbose }) = args.command {
+        print_version(verbose);
+        return;
+    }
+    let printer = Printer::new(args.no_color, &Config::default());
     let config = Config::load(args.config).unwrap_or_else(|err| {
-        if let Err(err) = printer.print_error_message(&err.to_string()) {
-            eprintln!("Error: {err}");
-        }
-        process::exit(1);
-    });
-    let chatbot = create_chatbot(args.command, &config).unwrap_or_else(|err| {
-        if let Err(err) = printer.print_error_message(&err.to_string()) {
-            eprintln!("Error: {err}");
-        }
-        process::exit(1);
+        report_error(&printer, json, &err.to_string());
+        process::exit(EXIT_CONFIG);
     });
+    let printer = Printer::new(args.no_color, &config);
 
-    let mut session = Session::new();
+    let subcommand_result = match args.command {
+        Some(CliCommand::Sessions { size }) => {
+            Some(print_sessions(&config, json, size))
+        }
+        Some(CliCommand::Validate { ref name }) => {
+            Some(validate_session(&printer, &config, name))
+        }
+        Some(CliCommand::ExportAll { ref file }) => {
+            Some(export_sessions(&printer, &config, file))
+        }
+        Some(CliCommand::ImportAll { ref file, force }) => {
+            Some(import_sessions(&printer, &config, file, force))
+        }
+        Some(
+            CliCommand::Gemini { .. }
+            | CliCommand::Huggingface { .. }
+            | CliCommand::Perplexity { .. }
+            | CliCommand::Openai { .. }
+            | CliCommand::Dummy
+            | CliCommand::Doctor { .. }
+            | CliCommand::Version { .. }
+            | _,
+        )
+        | None => None,
+    };
 
-    if let Some(system_prompt) = args.system_prompt {
-        session.add_message(Role::System, system_prompt);
+    if let Some(result) = subcommand_result {
+        if let Err(err) = result {
+            report_error(&printer, json, &err.to_string());
+            process::exit(EXIT_FAILURE);
+        }
+        return;
     }
 
-    let markdown_disabled = args
-        .no_markdown
-        .unwrap_or_else(|| config.markdown_disabled.unwrap_or_default());
+    let chatbot = create_chatbot(args.command, args.api_key, &config)
+        .await
+        .unwrap_or_else(|err| {
+            report_error(&printer, json, &err.to_string());
+            process::exit(creation_exit_code(&err));
+        });
+
+    let mut session = Session::new();
 
-    let mut app = App::new(chatbot, &printer, session, markdown_disabled);
+    session.conversation.seed_system_prompt(
+        args.system_prompt
+            .as_deref()
+            .or(config.default_system_prompt.as_deref()),
+    );
+
+    let mut app = App::new(
+        chatbot,
+        Printer::new(args.no_color, &config),
+        session,
+        config,
+        args.no_markdown,
+        args.ephemeral,
+    );
 
     let res = if let Some(prompt) = args.prompt {
-        app.run_single_prompt(prompt).await
+        app.run_single_prompt(prompt, json).await
     } else {
-        app.run_repl(config).await
+        app.run_repl().await
     };
 
     if let Err(err) = res {
-        if let Err(err) = printer.print_error_message(&err.to_string()) {
-            eprintln!("Error printing message: {err}");
-        }
+        let body = match err {
+            ChatError::Chatbot(ChatbotChatError::UnexpectedResponse {
+                ref body,
+            }) if args.verbose => body.as_str(),
+            ChatError::Read(_)
+            | ChatError::Print(_)
+            | ChatError::Readline(_)
+            | ChatError::Chatbot(_)
+            | ChatError::History(_)
+            | ChatError::Json(_)
+            | ChatError::Session(_)
+            | ChatError::Interrupted(_)
+            | ChatError::EmptyPrompt
+            | ChatError::Cancelled
+            | ChatError::Quit => "",
+        };
+        let message = if body.is_empty() {
+            err.to_string()
+        } else {
+            format!("{err}\nResponse body:\n{body}")
+        };
+        report_error(&printer, json, &message);
         if !matches!(err, ChatError::Quit)
             && !matches!(err, ChatError::Readline(ReadlineError::Interrupted))
         {
-            process::exit(1);
+            process::exit(err.exit_code());
         }
     }
 }
 
-fn create_chatbot(
-    chatbot: Option<ChatbotArg>,
+fn init_logging(verbose: bool) {
+    let filter = if verbose {
+        EnvFilter::new("llmcli=debug")
+    } else {
+        EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::new("warn"))
+    };
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .without_time()
+        .init();
+}
+
+fn report_error(printer: &Printer, json: bool, message: &str) {
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({ "error": redact_secrets(message) })
+        );
+        return;
+    }
+
+    if let Err(err) = printer.print_error_message(message) {
+        eprintln!("Error printing message: {err}");
+    }
+}
+
+fn print_sessions(
     config: &Config,
-) -> Result<Box<dyn Chatbot>, ChatbotCreationError> {
-    match chatbot {
-        Some(ChatbotArg::Gemini { model }) => {
-            let api_key = config
-                .api_keys
-                .as_ref()
-                .and_then(|api_keys| api_keys.gemini.clone());
-
-            GeminiChatbot::create(model.to_string(), api_key)
+    json: bool,
+    size: bool,
+) -> Result<(), ChatError> {
+    let sessions = Session::list_info(config)?;
+
+    if json {
+        println!("{}", serde_json::to_string(&sessions)?);
+        return Ok(());
+    }
+
+    for session in &sessions {
+        if size {
+            println!("{}\t{}", session.name, ui::format_bytes(session.size));
+        } else {
+            println!("{}", session.name);
         }
-        Some(ChatbotArg::Dummy) => DummyChatbot::create(String::new(), None),
-        Some(_) => Err(ChatbotCreationError::UnknownChatbot),
-        None => {
-            let default_chatbot = config
-                .default_chatbot
-                .as_ref()
-                .ok_or(ChatbotCreationError::UnknownChatbot)?;
-
-            let api_keys = config.api_keys.as_ref();
-
-            match default_chatbot.as_str() {
-                "gemini" => GeminiChatbot::create(
-                    config
-                        .default_models
-                        .as_ref()
-                        .and_then(|models| models.gemini.clone())
-                        .ok_or(ChatbotCreationError::UnknownModel)?,
-                    api_keys.and_then(|api_keys| api_keys.gemini.clone()),
-                ),
-                "dummy" => DummyChatbot::create(String::new(), None),
-                _ => Err(ChatbotCreationError::UnknownChatbot),
+    }
+
+    if size {
+        let total = sessions.iter().map(|session| session.size).sum();
+        println!(
+            "{} sessions, {} total",
+            sessions.len(),
+            ui::format_bytes(total)
+        );
+    }
+
+    Ok(())
+}
+
+fn validate_session(
+    printer: &Printer,
+    config: &Config,
+    name: &str,
+) -> Result<(), ChatError> {
+    Session::validate_file(name, config)?;
+
+    printer
+        .print_app_message(&format!("Session {name} is valid."))
+        .map_err(ChatError::Print)
+}
+
+fn export_sessions(
+    printer: &Printer,
+    config: &Config,
+    file: &Path,
+) -> Result<(), ChatError> {
+    let count = Session::export_all(file, config)?;
+
+    printer
+        .print_app_message(&format!(
+            "Exported {count} sessions to {}.",
+            file.display()
+        ))
+        .map_err(ChatError::Print)
+}
+
+fn import_sessions(
+    printer: &Printer,
+    config: &Config,
+    file: &Path,
+    force: bool,
+) -> Result<(), ChatError> {
+    let mut imported: usize = 0;
+
+    for (name, mut session) in Session::read_archive(file)? {
+        if !force && Session::exists(&name, config)? {
+            let overwrite = io::stdin().is_terminal()
+                && printer
+                    .confirm(&format!("Overwrite session {name}?"))
+                    .map_err(ChatError::Print)?;
+            if !overwrite {
+                printer
+                    .print_app_message(&format!("Skipped session {name}."))
+                    .map_err(ChatError::Print)?;
+                continue;
             }
         }
+
+        session.save(&name, config)?;
+        imported = imported.saturating_add(1);
+    }
+
+    printer
+        .print_app_message(&format!("Imported {imported} sessions."))
+        .map_err(ChatError::Print)
+}
+
+#[derive(Clone, Copy)]
+enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Default)]
+struct Doctor {
+    failed: bool,
+}
+
+impl Doctor {
+    fn report(&mut self, status: CheckStatus, message: &str) {
+        let label = match status {
+            CheckStatus::Pass => "[ok]  ",
+            CheckStatus::Warn => "[warn]",
+            CheckStatus::Fail => {
+                self.failed = true;
+                "[fail]"
+            }
+        };
+        println!("{label} {message}");
+    }
+
+    fn report_writable(&mut self, what: &str, dir: &Path) {
+        let probe = dir.join(".llmcli-doctor");
+        let result =
+            fs::write(&probe, b"").and_then(|()| fs::remove_file(&probe));
+
+        match result {
+            Ok(()) => self.report(
+                CheckStatus::Pass,
+                &format!("{what} {} is writable", dir.display()),
+            ),
+            Err(err) => self.report(
+                CheckStatus::Fail,
+                &format!("{what} {} is not writable: {err}", dir.display()),
+            ),
+        }
+    }
+}
+
+fn print_version(verbose: bool) {
+    println!("llmcli {}", env!("CARGO_PKG_VERSION"));
+    if !verbose {
+        return;
+    }
+
+    let build_date = env!("LLMCLI_BUILD_TIME")
+        .parse()
+        .ok()
+        .and_then(|secs| DateTime::<Utc>::from_timestamp(secs, 0))
+        .map_or_else(
+            || "unknown".to_owned(),
+            |time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        );
+
+    println!("commit:     {}", env!("LLMCLI_GIT_COMMIT"));
+    println!("build date: {build_date}");
+    println!("rustc:      {}", env!("LLMCLI_RUSTC_VERSION"));
+    println!("target:     {}-{}", env::consts::ARCH, env::consts::OS);
+}
+
+async fn run_doctor(cli_config: Option<PathBuf>, ping: bool) -> bool {
+    let mut doctor = Doctor::default();
+
+    let config = match Config::load(cli_config) {
+        Ok(config) => {
+            doctor.report(CheckStatus::Pass, "Config file parsed");
+            config
+        }
+        Err(err) => {
+            doctor.report(CheckStatus::Fail, &format!("Config file: {err}"));
+            Config::default()
+        }
+    };
+
+    match Session::get_dir_path(&config) {
+        Ok(dir) => doctor.report_writable("Session directory", &dir),
+        Err(err) => doctor
+            .report(CheckStatus::Fail, &format!("Session directory: {err}")),
+    }
+
+    match history::locate_file(&config) {
+        Ok(file) => doctor.report_writable(
+            "History directory",
+            file.parent().unwrap_or_else(|| Path::new(".")),
+        ),
+        Err(err) => doctor
+            .report(CheckStatus::Fail, &format!("History directory: {err}")),
+    }
+
+    let providers: Vec<&str> = registry::list(&config)
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|&name| name != "dummy")
+        .collect();
+
+    let mut reachable = Vec::new();
+    for name in providers {
+        if config.api_key_for(name).is_some() {
+            doctor.report(CheckStatus::Pass, &format!("API key for {name}"));
+            reachable.push(name);
+            continue;
+        }
+
+        if config.provider(name).is_some() {
+            doctor.report(
+                CheckStatus::Warn,
+                &format!("No API key for {name}, fine for local servers"),
+            );
+            reachable.push(name);
+        } else if config.default_chatbot.as_deref() == Some(name) {
+            doctor.report(
+                CheckStatus::Fail,
+                &format!("No API key for {name}, the default chatbot"),
+            );
+        } else {
+            doctor.report(CheckStatus::Warn, &format!("No API key for {name}"));
+        }
+    }
+
+    if ping {
+        let probe = [Message::new(Role::User, "ping".to_owned())];
+        for name in reachable {
+            let result = match registry::create_by_name(name, &config).await {
+                Ok(chatbot) => chatbot
+                    .send_message(&probe)
+                    .await
+                    .map(|_| ())
+                    .map_err(|err| err.to_string()),
+                Err(err) => Err(err.to_string()),
+            };
+            match result {
+                Ok(()) => doctor
+                    .report(CheckStatus::Pass, &format!("{name} responded")),
+                Err(err) => {
+                    doctor.report(CheckStatus::Fail, &format!("{name}: {err}"));
+                }
+            }
+        }
+    }
+
+    !doctor.failed
+}
+
+async fn create_chatbot(
+    chatbot: Option<CliCommand>,
+    api_key: Option<String>,
+    config: &Config,
+) -> Result<Box<dyn Chatbot>, ChatbotCreationError> {
+    let (provider, model) = match chatbot {
+        Some(CliCommand::Gemini { model }) => ("gemini", Some(model)),
+        Some(CliCommand::Huggingface { model }) => ("huggingface", Some(model)),
+        Some(CliCommand::Perplexity { model }) => ("perplexity", Some(model)),
+        Some(CliCommand::Openai { model }) => ("openai", Some(model)),
+        Some(CliCommand::Dummy) => ("dummy", None),
+        Some(_) => return Err(ChatbotCreationError::UnknownChatbot),
+        None => (
+            config
+                .default_chatbot
+                .as_deref()
+                .ok_or(ChatbotCreationError::UnknownChatbot)?,
+            None,
+        ),
+    };
+
+    let mut builder = ChatClient::builder(provider);
+    if let Some(model) = model {
+        builder = builder.model(&model);
+    }
+    if let Some(api_key) = api_key {
+        builder = builder.api_key(&api_key);
     }
+
+    Ok(builder.build(config).await?.into_chatbot())
+}
+
+#[derive(Serialize)]
+struct JsonReply<'reply> {
+    provider: &'reply str,
+    model: &'reply str,
+    response: &'reply str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning: Option<&'reply str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    finish_reason: Option<&'reply str>,
+    usage: Option<Usage>,
 }
 
 #[derive(Debug, Error)]
@@ -115,35 +476,112 @@ enum ChatError {
     Chatbot(#[from] ChatbotChatError),
     #[error("{0}")]
     History(#[from] HistoryError),
+    #[error("Failed to serialize output: {0}.")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Session(#[from] SessionError),
+    #[error("Reply interrupted: {0}")]
+    Interrupted(ChatbotChatError),
+    #[error("The prompt is empty.")]
+    EmptyPrompt,
+    #[error("Request cancelled.")]
+    Cancelled,
     #[error("User quit.")]
     Quit,
 }
 
-struct App<'printer> {
+impl ChatError {
+    const fn exit_code(&self) -> i32 {
+        match *self {
+            Self::Chatbot(ref err) | Self::Interrupted(ref err) => {
+                chat_exit_code(err)
+            }
+            Self::Read(_)
+            | Self::Print(_)
+            | Self::Readline(_)
+            | Self::History(_)
+            | Self::Json(_)
+            | Self::Session(_)
+            | Self::Cancelled
+            | Self::Quit => EXIT_FAILURE,
+            Self::EmptyPrompt => EXIT_USAGE,
+        }
+    }
+}
+
+/// Trims surrounding whitespace off a prompt, or returns `None` if nothing is
+/// left worth sending.
+fn non_empty_prompt(input: &str) -> Option<String> {
+    let prompt = input.trim();
+    (!prompt.is_empty()).then(|| prompt.to_owned())
+}
+
+const fn chat_exit_code(err: &ChatbotChatError) -> i32 {
+    match *err {
+        ChatbotChatError::Timeout
+        | ChatbotChatError::NetworkError(_)
+        | ChatbotChatError::RateLimited { .. } => EXIT_NETWORK,
+        ChatbotChatError::Unauthorized { .. } => EXIT_AUTH,
+        ChatbotChatError::AttachmentsUnsupported { .. } => EXIT_USAGE,
+        ChatbotChatError::UnexpectedResponse { .. }
+        | ChatbotChatError::Blocked(_)
+        | ChatbotChatError::Cancelled
+        | _ => EXIT_FAILURE,
+    }
+}
+
+const fn creation_exit_code(err: &ChatbotCreationError) -> i32 {
+    match *err {
+        ChatbotCreationError::ApiKeyMissing => EXIT_AUTH,
+        ChatbotCreationError::UnknownChatbot
+        | ChatbotCreationError::Disabled { .. }
+        | ChatbotCreationError::UnknownModel => EXIT_USAGE,
+        ChatbotCreationError::InvalidProxy(_)
+        | ChatbotCreationError::CaCert(_)
+        | ChatbotCreationError::InvalidCaCert(_)
+        | ChatbotCreationError::DummyScript(_) => EXIT_CONFIG,
+        ChatbotCreationError::HttpClient(_) | _ => EXIT_FAILURE,
+    }
+}
+
+struct App {
     chatbot: Box<dyn Chatbot>,
-    printer: &'printer Printer,
+    printer: Printer,
     session: Session,
-    markdown_disabled: bool,
+    config: Config,
+    no_markdown: Option<bool>,
+    ephemeral: bool,
 }
 
-impl<'printer> App<'printer> {
+impl App {
     const fn new(
         chatbot: Box<dyn Chatbot>,
-        printer: &'printer Printer,
+        printer: Printer,
         session: Session,
-        markdown_disabled: bool,
+        config: Config,
+        no_markdown: Option<bool>,
+        ephemeral: bool,
     ) -> Self {
         Self {
             chatbot,
             printer,
             session,
-            markdown_disabled,
+            config,
+            no_markdown,
+            ephemeral,
         }
     }
 
+    fn markdown_disabled(&self) -> bool {
+        self.no_markdown.unwrap_or_else(|| {
+            self.config.markdown_disabled.unwrap_or_default()
+        })
+    }
+
     async fn run_single_prompt(
         &mut self,
         prompt: String,
+        json: bool,
     ) -> Result<(), ChatError> {
         let input = if prompt == "-" {
             let mut input = String::new();
@@ -154,9 +592,25 @@ impl<'printer> App<'printer> {
         } else {
             prompt
         };
+        let input = non_empty_prompt(&input).ok_or(ChatError::EmptyPrompt)?;
 
         self.session.add_message(Role::User, input);
 
+        if json {
+            self.trim_to_context();
+            let response = self.request_reply().await?;
+            let reply = JsonReply {
+                provider: &self.chatbot.name().to_lowercase(),
+                model: self.chatbot.model_id(),
+                response: &response.content,
+                reasoning: response.reasoning.as_deref(),
+                finish_reason: response.finish_reason.as_deref(),
+                usage: response.usage,
+            };
+            println!("{}", serde_json::to_string(&reply)?);
+            return Ok(());
+        }
+
         self.printer
             .print_chatbot_prefix(self.chatbot.name())
             .map_err(ChatError::Print)?;
@@ -166,49 +620,104 @@ impl<'printer> App<'printer> {
         Ok(())
     }
 
-    async fn run_repl(&mut self, config: Config) -> Result<(), ChatError> {
+    async fn run_repl(&mut self) -> Result<(), ChatError> {
         let mut rl = DefaultEditor::new()?;
-        let history_file = history::locate_file(&config)?;
-        rl.load_history(&*history_file)?;
-        let user_prefix = self.printer.get_user_prefix();
+        let history_file = if self.ephemeral {
+            None
+        } else {
+            let path = history::locate_file(&self.config)?.into_owned();
+            rl.load_history(&path)?;
+            Some(path)
+        };
+        let external_editor = ExternalEditor::new();
+        let keybinding = self
+            .config
+            .ui
+            .as_ref()
+            .and_then(|ui| ui.editor_keybinding.as_deref())
+            .unwrap_or(editor::DEFAULT_KEYBINDING);
+        if let Some(event) = editor::parse_keybinding(keybinding) {
+            rl.bind_sequence(
+                event,
+                EventHandler::Conditional(Box::new(external_editor.clone())),
+            );
+        } else {
+            self.printer
+                .print_error_message(&format!(
+                    "Invalid editor keybinding: {keybinding}"
+                ))
+                .map_err(ChatError::Print)?;
+        }
+        let mut interrupted = false;
 
         loop {
-            print!("{user_prefix}");
-            let input = match rl.readline("") {
-                Ok(line) => Ok(line),
+            let user_prefix = self.printer.get_user_prefix();
+            let (user_header, user_prompt) = user_prefix
+                .rsplit_once('\n')
+                .unwrap_or(("", user_prefix.as_str()));
+            let user_prompt = format!(
+                "{}{}{user_prompt}",
+                self.token_budget(),
+                self.printer.get_model_prompt(self.chatbot.model_id())
+            );
+            if !user_header.is_empty() {
+                println!("{user_header}");
+            }
+            let input = match rl.readline(&user_prompt) {
+                Ok(line) => external_editor.take_edited().unwrap_or(line),
+                Err(ReadlineError::Interrupted) if !interrupted => {
+                    interrupted = true;
+                    self.printer
+                        .print_app_message("Press Ctrl-C again to quit.")
+                        .map_err(ChatError::Print)?;
+                    continue;
+                }
                 Err(err) => {
                     if matches!(err, ReadlineError::Interrupted) {
-                        rl.save_history(&&*history_file)?;
+                        if let Some(path) = history_file.as_ref() {
+                            rl.save_history(path)?;
+                        }
                     }
-                    Err(err)
+                    break Err(err.into());
                 }
-            }?;
+            };
+
+            interrupted = false;
 
-            if input.trim().is_empty() {
+            let Some(input) = non_empty_prompt(&input) else {
                 continue;
-            }
+            };
 
-            if input.starts_with('/') {
+            let input = if input.starts_with('/') {
                 rl.add_history_entry(&input)?;
 
                 let parts: Vec<&str> = input.split_whitespace().collect();
 
                 let command = Command::from_parts(&parts);
 
-                match command {
+                let follow_up = match command {
                     Ok(command) => {
                         let mut context = CommandContext::new(
                             &parts,
                             &mut self.session,
                             &mut self.chatbot,
-                            self.printer,
-                            &config,
+                            &mut self.printer,
+                            &self.config,
                         );
 
-                        if let Err(err) = command.execute(&mut context) {
+                        let result = command.execute(&mut context).await;
+                        let follow_up = context.take_follow_up();
+                        if let Some(config) = context.take_reloaded_config() {
+                            self.printer.reconfigure(&config);
+                            self.config = config;
+                        }
+
+                        if let Err(err) = result {
                             match err {
                                 CommandExecuteError::Quit => {
-                                    rl.save_history(&&*history_file)?;
+                                    if let Some(path) = history_file.as_ref() {
+                                        rl.save_history(path)?;
+                                    }
                                     break Err(ChatError::Quit);
                                 }
                                 CommandExecuteError::Print(_)
@@ -220,22 +729,51 @@ impl<'printer> App<'printer> {
                                     .map_err(ChatError::Print)?,
                             }
                         }
-                    }
-                    Err(err) => self
-                        .printer
-                        .print_error_message(&err.to_string())
-                        .map_err(ChatError::Print)?,
-                }
-                continue;
-            }
 
+                        follow_up
+                    }
+                    Err(err) => {
+                        self.printer
+                            .print_error_message(&err.to_string())
+                            .map_err(ChatError::Print)?;
+                        None
+                    }
+                };
+
+                let Some(prompt) =
+                    follow_up.as_deref().and_then(non_empty_prompt)
+                else {
+                    continue;
+                };
+                prompt
+            } else {
+                input
+            };
+
+            self.warn_without_system()?;
             self.session.add_message(Role::User, input);
 
             self.printer
                 .print_chatbot_prefix(self.chatbot.name())
                 .map_err(ChatError::Print)?;
 
-            self.handle_chat_message().await?;
+            match self.handle_chat_message().await {
+                Ok(()) => {}
+                Err(ChatError::Cancelled) => {
+                    interrupted = true;
+                    self.printer
+                        .print_app_message(&ChatError::Cancelled.to_string())
+                        .map_err(ChatError::Print)?;
+                    continue;
+                }
+                Err(err @ ChatError::Interrupted(_)) => {
+                    self.printer
+                        .print_error_message(&err.to_string())
+                        .map_err(ChatError::Print)?;
+                    continue;
+                }
+                Err(err) => break Err(err),
+            }
 
             if !io::stdin().is_terminal() {
                 break Ok(());
@@ -243,16 +781,203 @@ impl<'printer> App<'printer> {
         }
     }
 
+    fn log_transcript(
+        &self,
+        speaker: &str,
+        text: &str,
+    ) -> Result<(), ChatError> {
+        if let Err(err) = self.printer.log_transcript(speaker, text) {
+            self.printer
+                .print_error_message(&format!(
+                    "Failed to write transcript log: {err}."
+                ))
+                .map_err(ChatError::Print)?;
+        }
+
+        Ok(())
+    }
+
+    fn warn_without_system(&mut self) -> Result<(), ChatError> {
+        if !self.config.warn_without_system.unwrap_or_default()
+            || self.session.warned_without_system
+            || self.session.conversation.system_prompt().is_some()
+        {
+            return Ok(());
+        }
+
+        self.session.warned_without_system = true;
+        self.printer
+            .print_app_message(
+                "No system prompt set, some providers answer better with \
+                 one. Use /system <prompt> to add it.",
+            )
+            .map_err(ChatError::Print)
+    }
+
+    fn token_budget(&self) -> String {
+        let context_window = self.chatbot.model_info().context_window;
+        match context_window {
+            Some(limit) if !self.printer.no_color() => format!(
+                "[{}/{}] ",
+                ui::format_tokens(self.session.conversation.estimated_tokens()),
+                ui::format_tokens(limit)
+            ),
+            Some(_) | None => String::new(),
+        }
+    }
+
+    fn trim_to_context(&mut self) -> usize {
+        self.chatbot
+            .model_info()
+            .context_window
+            .map_or(0, |context_window| {
+                self.session.conversation.trim_to(
+                    context_window
+                        .saturating_sub(self.session.context_tokens()),
+                )
+            })
+    }
+
+    async fn request_reply(&mut self) -> Result<ChatResponse, ChatError> {
+        let cancel = CancellationToken::new();
+        let messages = self
+            .session
+            .with_context(&self.session.conversation.messages);
+        let result = ui::cancel_on_keypress(
+            &cancel,
+            self.chatbot.send_message_cancellable(&messages, &cancel),
+        )
+        .await;
+
+        if matches!(result, Err(ChatbotChatError::Cancelled)) {
+            self.session.conversation.messages.pop();
+            return Err(ChatError::Cancelled);
+        }
+
+        Ok(result?)
+    }
+
+    fn streaming(&self) -> bool {
+        self.config.stream.unwrap_or_default()
+            && self.chatbot.model_info().supports_streaming
+    }
+
+    /// Prints the reply as it streams in. If the stream fails or is
+    /// cancelled midway, the text received so far is kept in the session
+    /// with a marker.
+    async fn stream_reply(&mut self) -> Result<ChatResponse, ChatError> {
+        let mut buffer = StreamBuffer::new();
+        let mut text = String::new();
+        let mut print_error = None;
+
+        let cancel = CancellationToken::new();
+        let result = {
+            let messages = self
+                .session
+                .with_context(&self.session.conversation.messages);
+            let printer = &self.printer;
+            let mut on_chunk = |chunk: &str| {
+                text.push_str(chunk);
+                if let Err(err) = printer.print_stream_chunk(&mut buffer, chunk)
+                {
+                    print_error.get_or_insert(err);
+                }
+            };
+            ui::cancel_on_keypress(
+                &cancel,
+                self.chatbot
+                    .stream_message(&messages, &mut on_chunk, &cancel),
+            )
+            .await
+        };
+
+        self.printer
+            .finish_stream(&mut buffer)
+            .map_err(ChatError::Print)?;
+        if let Some(err) = print_error {
+            return Err(ChatError::Print(err));
+        }
+
+        let err = match result {
+            Ok(response) => {
+                if let Some(reasoning) = response.reasoning.as_deref() {
+                    self.printer
+                        .print_reasoning(reasoning)
+                        .map_err(ChatError::Print)?;
+                }
+                return Ok(response);
+            }
+            Err(ChatbotChatError::Cancelled) if text.is_empty() => {
+                self.session.conversation.messages.pop();
+                return Err(ChatError::Cancelled);
+            }
+            Err(ChatbotChatError::Cancelled) => ChatError::Cancelled,
+            Err(err) if text.is_empty() => return Err(err.into()),
+            Err(err) => ChatError::Interrupted(err),
+        };
+
+        self.log_transcript(self.chatbot.name(), &text)?;
+        self.session.add_message(
+            Role::Assistant,
+            format!("{text}\n\n{PARTIAL_REPLY_MARKER}"),
+        );
+
+        Err(err)
+    }
+
     async fn handle_chat_message(&mut self) -> Result<(), ChatError> {
-        let result = self.chatbot.send_message(&self.session.messages).await?;
+        if let Some(prompt) = self.session.conversation.messages.last() {
+            self.log_transcript("user", &prompt.content)?;
+        }
 
-        if self.markdown_disabled {
-            print!("{result}");
+        let removed = self.trim_to_context();
+        if removed > 0 {
+            self.printer
+                .print_app_message(&format!(
+                    "Dropped {removed} old messages to fit the context window."
+                ))
+                .map_err(ChatError::Print)?;
+        }
+
+        let response = if self.streaming() {
+            self.stream_reply().await?
         } else {
-            termimad::print_text(&result);
+            let response = self.request_reply().await?;
+            if let Some(reasoning) = response.reasoning.as_deref() {
+                self.printer
+                    .print_reasoning(reasoning)
+                    .map_err(ChatError::Print)?;
+            }
+            self.printer
+                .print_reply(&response.content, self.markdown_disabled())
+                .map_err(ChatError::Print)?;
+            response
+        };
+        self.log_transcript(self.chatbot.name(), &response.content)?;
+
+        if response.is_truncated() {
+            self.printer
+                .print_app_message("The reply was cut off at the token limit.")
+                .map_err(ChatError::Print)?;
+        }
+
+        if let Some(usage) = response.usage {
+            if let Some(cost) = self.config.cost_for(
+                &self.chatbot.name().to_lowercase(),
+                self.chatbot.model_id(),
+                usage,
+            ) {
+                self.printer
+                    .print_app_message(&format!(
+                        "Cost: ${cost:.6} ({} prompt + {} completion tokens)",
+                        usage.prompt, usage.completion
+                    ))
+                    .map_err(ChatError::Print)?;
+            }
         }
 
-        self.session.add_message(Role::Assistant, result);
+        self.session.last_response = response.raw;
+        self.session.add_message(Role::Assistant, response.content);
 
         Ok(())
     }
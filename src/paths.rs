@@ -0,0 +1,135 @@
+use std::{env, path::PathBuf};
+
+const FALLBACK_DIR: &str = ".llmcli";
+
+#[inline]
+#[must_use]
+pub fn config_file() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME")
+        .or_else(dirs::config_dir)
+        .map_or_else(
+            || fallback_dir().join("config.toml"),
+            |dir| dir.join("llmcli").join("config.toml"),
+        )
+}
+
+/// `LLMCLI_SESSION_DIR` replaces the platform data directory, e.g. to keep
+/// scripted runs away from real sessions. A `session_path` in the config
+/// still takes precedence.
+#[inline]
+#[must_use]
+pub fn session_dir() -> PathBuf {
+    if let Some(dir) =
+        env::var_os("LLMCLI_SESSION_DIR").filter(|dir| !dir.is_empty())
+    {
+        return PathBuf::from(dir);
+    }
+
+    xdg_dir("XDG_DATA_HOME")
+        .or_else(dirs::data_dir)
+        .map_or_else(
+            || fallback_dir().join("sessions"),
+            |dir| dir.join("llmcli_sessions"),
+        )
+}
+
+#[inline]
+#[must_use]
+pub fn history_file() -> PathBuf {
+    xdg_dir("XDG_CACHE_HOME")
+        .or_else(dirs::cache_dir)
+        .map_or_else(
+            || fallback_dir().join("history.txt"),
+            |dir| dir.join("llmcli_history.txt"),
+        )
+}
+
+/// Reads an XDG base directory override. `dirs` only honors these on Linux,
+/// and the specification says relative paths must be ignored.
+fn xdg_dir(var: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+}
+
+/// Used when the platform directories cannot be determined, as is common in
+/// minimal containers: `$HOME/.llmcli`, or `.llmcli` in the current directory.
+fn fallback_dir() -> PathBuf {
+    env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::current_dir().ok())
+        .unwrap_or_default()
+        .join(FALLBACK_DIR)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsString, path::Path, sync::Mutex};
+
+    use super::*;
+
+    /// Tests in this module change process-wide variables, so they take
+    /// turns.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Sets variables for the length of a test and restores them on drop.
+    struct EnvVars(Vec<(&'static str, Option<OsString>)>);
+
+    impl EnvVars {
+        fn set(vars: &[(&'static str, Option<&str>)]) -> Self {
+            let saved = vars
+                .iter()
+                .map(|&(name, value)| {
+                    let old = env::var_os(name);
+                    match value {
+                        Some(value) => env::set_var(name, value),
+                        None => env::remove_var(name),
+                    }
+                    (name, old)
+                })
+                .collect();
+            Self(saved)
+        }
+    }
+
+    impl Drop for EnvVars {
+        fn drop(&mut self) {
+            for (name, old) in self.0.drain(..) {
+                match old {
+                    Some(value) => env::set_var(name, value),
+                    None => env::remove_var(name),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn absolute_xdg_overrides_are_used() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        let _vars = EnvVars::set(&[
+            ("LLMCLI_SESSION_DIR", None),
+            ("XDG_DATA_HOME", Some("/tmp/xdg-data")),
+            ("XDG_CACHE_HOME", Some("/tmp/xdg-cache")),
+        ]);
+
+        assert_eq!(session_dir(), Path::new("/tmp/xdg-data/llmcli_sessions"));
+        assert_eq!(
+            history_file(),
+            Path::new("/tmp/xdg-cache/llmcli_history.txt"),
+        );
+    }
+
+    #[test]
+    fn relative_xdg_overrides_are_ignored() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        let _vars = EnvVars::set(&[
+            ("LLMCLI_SESSION_DIR", None),
+            ("XDG_DATA_HOME", Some("relative/data")),
+            ("XDG_CACHE_HOME", Some("relative/cache")),
+        ]);
+
+        assert!(!session_dir().starts_with("relative"));
+        assert!(!history_file().starts_with("relative"));
+    }
+}
@@ -1,16 +1,125 @@
-use alloc::borrow::Cow;
-use std::{ffi::OsStr, fs, path::PathBuf};
+use alloc::{borrow::Cow, collections::BTreeMap};
+use core::mem;
+use std::{
+    fs::{self, OpenOptions},
+    io::{Read as _, Write as _},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use futures::io;
+use glob::Pattern;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::{config::Config, Message, Role};
+use crate::{
+    config::Config, conversation::Conversation, paths, Attachment, Message,
+    Role,
+};
+
+mod crypto;
+
+const EXTENSIONS: [(SessionFormat, bool, &str); 4] = [
+    (SessionFormat::Json, true, "json.gz"),
+    (SessionFormat::Jsonl, true, "jsonl.gz"),
+    (SessionFormat::Json, false, "json"),
+    (SessionFormat::Jsonl, false, "jsonl"),
+];
+const SNIPPET_CONTEXT: usize = 30;
+const MAX_UNDO_STEPS: usize = 20;
+const CONTEXT_PREAMBLE: &str =
+    "The user attached the following sources as context for this conversation.";
 
 #[non_exhaustive]
 #[derive(Serialize, Deserialize, Default)]
 pub struct Session {
-    pub messages: Vec<Message>,
+    #[serde(flatten)]
+    pub conversation: Conversation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip)]
+    pub current_name: Option<String>,
+    #[serde(skip)]
+    pub pending_attachments: Vec<Attachment>,
+    #[serde(skip)]
+    pub warned_without_system: bool,
+    #[serde(skip)]
+    pub redo_stack: Vec<Vec<Message>>,
+    #[serde(skip)]
+    pub last_response: Option<serde_json::Value>,
+    #[serde(skip)]
+    pub context: Vec<ContextBlob>,
+    #[serde(skip)]
+    pub saved_jsonl: Option<SavedJsonl>,
+}
+
+/// What the last plain JSONL save wrote, so the next one can append only the
+/// messages added since. Any other change to the history rewrites the file.
+#[non_exhaustive]
+pub struct SavedJsonl {
+    name: String,
+    header: Vec<u8>,
+    messages: Vec<Message>,
+}
+
+/// On-disk layout of saved sessions. `Jsonl` writes a header line followed by
+/// one message per line, so saving a grown session only appends.
+#[non_exhaustive]
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionFormat {
+    #[default]
+    Json,
+    Jsonl,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonlHeader<'text> {
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    provider: Option<Cow<'text, str>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    model: Option<Cow<'text, str>>,
+}
+
+#[non_exhaustive]
+#[derive(Serialize)]
+pub struct SessionInfo {
+    pub name: String,
+    pub modified: Option<u64>,
+    pub size: u64,
+}
+
+#[non_exhaustive]
+pub struct SessionHit {
+    pub name: String,
+    pub role: Role,
+    pub snippet: String,
+}
+
+/// A file or web page attached with `/context add`, sent along with every
+/// request until cleared.
+#[non_exhaustive]
+#[derive(Clone)]
+pub struct ContextBlob {
+    pub source: String,
+    pub content: String,
+}
+
+impl ContextBlob {
+    #[inline]
+    #[must_use]
+    pub const fn new(source: String, content: String) -> Self {
+        Self { source, content }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn estimated_tokens(&self) -> usize {
+        self.content.chars().count().div_ceil(4)
+    }
 }
 
 #[non_exhaustive]
@@ -32,6 +141,29 @@ pub enum SessionError {
     NotFound,
     #[error("Failed to delete file: {0}.")]
     DeleteFile(io::Error),
+    #[error("Failed to encrypt session.")]
+    Encrypt,
+    #[error("Failed to decrypt session, wrong passphrase or corrupted file.")]
+    Decrypt,
+    #[error("Session passphrase missing, set LLMCLI_SESSION_PASSPHRASE.")]
+    PassphraseMissing,
+    #[error("Failed to read passphrase: {0}.")]
+    Passphrase(dialoguer::Error),
+    #[error("Failed to compress session: {0}.")]
+    Compress(io::Error),
+    #[error("Invalid session pattern: {0}.")]
+    InvalidPattern(#[from] glob::PatternError),
+    #[error("Invalid session file: {0}.")]
+    Invalid(serde_json::Error),
+    #[error("Invalid session file at line {line}: {source}.")]
+    InvalidLine {
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("Invalid session file, message {index}: {reason}.")]
+    InvalidMessage { index: usize, reason: &'static str },
+    #[error("Failed to decompress session: {0}.")]
+    Decompress(io::Error),
 }
 
 impl Session {
@@ -39,83 +171,436 @@ impl Session {
     #[must_use]
     pub const fn new() -> Self {
         Self {
-            messages: Vec::new(),
+            conversation: Conversation::new(),
+            provider: None,
+            model: None,
+            current_name: None,
+            pending_attachments: Vec::new(),
+            warned_without_system: false,
+            redo_stack: Vec::new(),
+            last_response: None,
+            context: Vec::new(),
+            saved_jsonl: None,
         }
     }
 
     #[inline]
     pub fn save(
-        &self,
+        &mut self,
         filename: &str,
         config: &Config,
     ) -> Result<(), SessionError> {
         let session_dir = Self::get_dir_path(config)?;
-        let file_path = session_dir.join(filename).with_extension("json");
-        let serialized = serde_json::to_string(self)?;
+        let format = config.session_format.unwrap_or_default();
+        let compressed = config.compress_sessions.unwrap_or_default();
+        let encrypted = config.encrypt_sessions.unwrap_or_default();
+        let file_path =
+            session_file(&session_dir, filename, format, compressed);
+
+        if format == SessionFormat::Jsonl && !compressed && !encrypted {
+            self.write_jsonl(&file_path, filename)?;
+        } else {
+            let mut contents = match format {
+                SessionFormat::Json => serde_json::to_vec_pretty(self)?,
+                SessionFormat::Jsonl => self.to_jsonl()?,
+            };
+            if compressed {
+                contents = compress(&contents)?;
+            }
+            if encrypted {
+                contents = crypto::encrypt(&contents)?;
+            }
+            fs::write(&file_path, contents).map_err(SessionError::WriteFile)?;
+        }
+
+        for &(stale_format, stale_compressed, _) in &EXTENSIONS {
+            if (stale_format, stale_compressed) == (format, compressed) {
+                continue;
+            }
+            let stale_path = session_file(
+                &session_dir,
+                filename,
+                stale_format,
+                stale_compressed,
+            );
+            if stale_path.exists() {
+                fs::remove_file(stale_path)
+                    .map_err(SessionError::DeleteFile)?;
+            }
+        }
 
-        fs::write(&file_path, serialized).map_err(SessionError::WriteFile)?;
+        self.current_name = Some(filename.to_owned());
 
         Ok(())
     }
 
+    #[inline]
+    pub fn exists(
+        filename: &str,
+        config: &Config,
+    ) -> Result<bool, SessionError> {
+        let session_dir = Self::get_dir_path(config)?;
+
+        Ok(find_session_file(&session_dir, filename).is_some())
+    }
+
     #[inline]
     pub fn load(filename: &str, config: &Config) -> Result<Self, SessionError> {
+        let mut session = Self::read_file(filename, config)?;
+        session.current_name = Some(filename.to_owned());
+
+        Ok(session)
+    }
+
+    #[inline]
+    pub fn validate_file(
+        filename: &str,
+        config: &Config,
+    ) -> Result<(), SessionError> {
+        let session = Self::read_file(filename, config)?;
+
+        check_roles(&session.conversation.messages)
+    }
+
+    fn read_file(
+        filename: &str,
+        config: &Config,
+    ) -> Result<Self, SessionError> {
         let session_dir = Self::get_dir_path(config)?;
-        let file_path = session_dir.join(filename).with_extension("json");
-        let file_content =
-            fs::read_to_string(file_path).map_err(SessionError::ReadFile)?;
-        let session: Self = serde_json::from_str(&file_content)?;
+        let file_path = find_session_file(&session_dir, filename)
+            .ok_or(SessionError::NotFound)?;
+        let mut file_content =
+            fs::read(&file_path).map_err(SessionError::ReadFile)?;
+        if crypto::is_encrypted(&file_content) {
+            file_content = crypto::decrypt(&file_content)?;
+        }
+        if is_compressed(&file_path) {
+            file_content = decompress(&file_content)?;
+        }
+
+        match format_of(&file_path) {
+            SessionFormat::Json => serde_json::from_slice(&file_content)
+                .map_err(SessionError::Invalid),
+            SessionFormat::Jsonl => Self::from_jsonl(&file_content),
+        }
+    }
+
+    fn to_jsonl(&self) -> Result<Vec<u8>, SessionError> {
+        let mut contents = self.jsonl_header()?;
+        push_jsonl_lines(&mut contents, &self.conversation.messages)?;
+
+        Ok(contents)
+    }
+
+    fn jsonl_header(&self) -> Result<Vec<u8>, SessionError> {
+        let header = JsonlHeader {
+            provider: self.provider.as_deref().map(Cow::Borrowed),
+            model: self.model.as_deref().map(Cow::Borrowed),
+        };
+        let mut line = serde_json::to_vec(&header)?;
+        line.push(b'\n');
+
+        Ok(line)
+    }
+
+    /// Appends the messages added since the last save of `filename`, or
+    /// writes the whole file if anything else changed in between.
+    fn write_jsonl(
+        &mut self,
+        path: &Path,
+        filename: &str,
+    ) -> Result<(), SessionError> {
+        let header = self.jsonl_header()?;
+        let messages = &self.conversation.messages;
+        let saved = self.saved_jsonl.take().filter(|saved| {
+            saved.name == filename
+                && saved.header == header
+                && messages.starts_with(&saved.messages)
+                && path.exists()
+        });
+
+        let saved = if let Some(mut saved) = saved {
+            let new = messages.get(saved.messages.len()..).unwrap_or_default();
+            let mut lines = Vec::new();
+            push_jsonl_lines(&mut lines, new)?;
+            OpenOptions::new()
+                .append(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(&lines))
+                .map_err(SessionError::WriteFile)?;
+            saved.messages.extend_from_slice(new);
+            saved
+        } else {
+            let mut contents = header.clone();
+            push_jsonl_lines(&mut contents, messages)?;
+            fs::write(path, contents).map_err(SessionError::WriteFile)?;
+            SavedJsonl {
+                name: filename.to_owned(),
+                header,
+                messages: messages.clone(),
+            }
+        };
+        self.saved_jsonl = Some(saved);
+
+        Ok(())
+    }
+
+    fn from_jsonl(contents: &[u8]) -> Result<Self, SessionError> {
+        let complete = contents.ends_with(b"\n");
+        let mut lines = contents
+            .split(|&byte| byte == b'\n')
+            .enumerate()
+            .filter(|&(_, line)| !line.trim_ascii().is_empty())
+            .peekable();
+        let mut session = Self::new();
+
+        if let Some((_, line)) = lines.next() {
+            let header: JsonlHeader<'_> =
+                serde_json::from_slice(line).map_err(|source| {
+                    SessionError::InvalidLine { line: 1, source }
+                })?;
+            session.provider = header.provider.map(Cow::into_owned);
+            session.model = header.model.map(Cow::into_owned);
+        }
+
+        while let Some((index, line)) = lines.next() {
+            match serde_json::from_slice(line) {
+                Ok(msg) => session.conversation.messages.push(msg),
+                Err(err) if !complete && lines.peek().is_none() => {
+                    tracing::debug!(%err, "Skipping truncated last line");
+                }
+                Err(source) => {
+                    return Err(SessionError::InvalidLine {
+                        line: index.saturating_add(1),
+                        source,
+                    });
+                }
+            }
+        }
 
         Ok(session)
     }
 
     #[inline]
     pub fn list_all(config: &Config) -> Result<Vec<String>, SessionError> {
+        Ok(Self::list_info(config)?
+            .into_iter()
+            .map(|info| info.name)
+            .collect())
+    }
+
+    #[inline]
+    pub fn list_info(
+        config: &Config,
+    ) -> Result<Vec<SessionInfo>, SessionError> {
         let session_dir = Self::get_dir_path(config)?;
         let entries =
             fs::read_dir(&*session_dir).map_err(SessionError::ReadDir)?;
-        let session_files: Vec<String> = entries
+        let mut sessions: Vec<SessionInfo> = entries
             .filter_map(Result::ok)
-            .filter(|file| file.path().extension() == Some(OsStr::new("json")))
-            .map(|file| {
-                file.file_name()
-                    .to_string_lossy()
-                    .trim_end_matches(".json")
-                    .to_owned()
+            .filter_map(|file| {
+                let file_name = file.file_name().to_string_lossy().into_owned();
+                let name = EXTENSIONS
+                    .iter()
+                    .find_map(|&(_, _, extension)| {
+                        file_name.strip_suffix(&format!(".{extension}"))
+                    })?
+                    .to_owned();
+                let metadata = file.metadata().ok();
+                Some(SessionInfo {
+                    name,
+                    modified: metadata
+                        .as_ref()
+                        .and_then(|metadata| metadata.modified().ok())
+                        .and_then(unix_seconds),
+                    size: metadata.map_or(0, |metadata| metadata.len()),
+                })
             })
             .collect();
 
-        Ok(session_files)
+        sessions.sort_unstable_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+        sessions.dedup_by(|lhs, rhs| lhs.name == rhs.name);
+
+        Ok(sessions)
+    }
+
+    /// Returns the saved session names matching a glob such as `test-*`.
+    #[inline]
+    pub fn matching(
+        pattern: &str,
+        config: &Config,
+    ) -> Result<Vec<String>, SessionError> {
+        let pattern = Pattern::new(pattern)?;
+
+        Ok(Self::list_all(config)?
+            .into_iter()
+            .filter(|name| pattern.matches(name))
+            .collect())
+    }
+
+    #[inline]
+    pub fn export_all(
+        path: &Path,
+        config: &Config,
+    ) -> Result<usize, SessionError> {
+        let sessions = Self::list_all(config)?
+            .into_iter()
+            .map(|name| {
+                let session = Self::load(&name, config)?;
+                Ok((name, session))
+            })
+            .collect::<Result<BTreeMap<_, _>, SessionError>>()?;
+
+        fs::write(path, serde_json::to_string_pretty(&sessions)?)
+            .map_err(SessionError::WriteFile)?;
+
+        Ok(sessions.len())
+    }
+
+    #[inline]
+    pub fn read_archive(
+        path: &Path,
+    ) -> Result<BTreeMap<String, Self>, SessionError> {
+        let file_content =
+            fs::read_to_string(path).map_err(SessionError::ReadFile)?;
+
+        Ok(serde_json::from_str(&file_content)?)
+    }
+
+    #[inline]
+    pub fn search(
+        query: &str,
+        config: &Config,
+    ) -> Result<Vec<SessionHit>, SessionError> {
+        let mut hits = Vec::new();
+
+        for name in Self::list_all(config)? {
+            let session = match Self::load(&name, config) {
+                Ok(session) => session,
+                Err(err) => {
+                    tracing::debug!(%err, session = %name, "Skipping session");
+                    continue;
+                }
+            };
+
+            hits.extend(session.conversation.messages.iter().filter_map(
+                |msg| {
+                    Some(SessionHit {
+                        name: name.clone(),
+                        role: msg.role,
+                        snippet: find_snippet(&msg.content, query)?,
+                    })
+                },
+            ));
+        }
+
+        Ok(hits)
     }
 
     #[inline]
     pub fn delete(filename: &str, config: &Config) -> Result<(), SessionError> {
         let session_dir = Self::get_dir_path(config)?;
-        let file_path = session_dir.join(filename).with_extension("json");
+        let file_path = find_session_file(&session_dir, filename)
+            .ok_or(SessionError::NotFound)?;
 
-        if file_path.exists() {
-            fs::remove_file(file_path).map_err(SessionError::DeleteFile)?;
+        fs::remove_file(file_path).map_err(SessionError::DeleteFile)
+    }
 
-            Ok(())
-        } else {
-            Err(SessionError::NotFound)
+    /// Removes the last user turn and everything after it, or the last
+    /// message if there is no user turn, returning how many were removed.
+    #[inline]
+    pub fn undo(&mut self) -> Option<usize> {
+        let messages = &mut self.conversation.messages;
+        let start = messages
+            .iter()
+            .rposition(|msg| msg.role == Role::User)
+            .or_else(|| {
+                messages
+                    .iter()
+                    .rposition(|msg| msg.role != Role::System)
+                    .filter(|&index| index.saturating_add(1) == messages.len())
+            })?;
+
+        let removed: Vec<Message> = messages.drain(start..).collect();
+        let count = removed.len();
+        if self.redo_stack.len() >= MAX_UNDO_STEPS {
+            self.redo_stack.remove(0);
         }
+        self.redo_stack.push(removed);
+
+        Some(count)
+    }
+
+    #[inline]
+    pub fn redo(&mut self) -> Option<usize> {
+        let restored = self.redo_stack.pop()?;
+        let count = restored.len();
+        self.conversation.messages.extend(restored);
+
+        Some(count)
+    }
+
+    #[inline]
+    pub fn clear_redo(&mut self) {
+        self.redo_stack.clear();
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn context_tokens(&self) -> usize {
+        self.context.iter().map(ContextBlob::estimated_tokens).sum()
+    }
+
+    /// Returns `messages` with the attached context injected as a system
+    /// message right after the system prompt.
+    #[inline]
+    #[must_use]
+    pub fn with_context<'messages>(
+        &self,
+        messages: &'messages [Message],
+    ) -> Cow<'messages, [Message]> {
+        if self.context.is_empty() {
+            return Cow::Borrowed(messages);
+        }
+
+        let mut sections = vec![CONTEXT_PREAMBLE.to_owned()];
+        sections.extend(
+            self.context.iter().map(|blob| {
+                format!("=== {} ===\n{}", blob.source, blob.content)
+            }),
+        );
+
+        let position = messages
+            .iter()
+            .take_while(|msg| msg.role == Role::System)
+            .count();
+        let mut messages = messages.to_vec();
+        messages.insert(
+            position,
+            Message::new(Role::System, sections.join("\n\n")),
+        );
+
+        Cow::Owned(messages)
     }
 
     #[inline]
     pub fn add_message(&mut self, role: Role, content: String) {
-        self.messages.push(Message::new(role, content));
+        self.redo_stack.clear();
+        let msg = self.conversation.add_message(role, content);
+        if role == Role::User {
+            msg.attachments = mem::take(&mut self.pending_attachments);
+        }
     }
 
-    fn get_dir_path(config: &Config) -> Result<Cow<'_, PathBuf>, SessionError> {
+    #[inline]
+    pub fn get_dir_path(
+        config: &Config,
+    ) -> Result<Cow<'_, Path>, SessionError> {
         if let Some(ref path) = config.session_path {
             return Ok(Cow::Borrowed(path));
         }
 
-        let session_dir = {
-            let data_dir = dirs::data_dir().ok_or(SessionError::DataDir)?;
-            data_dir.join("llmcli_sessions")
-        };
+        let session_dir = paths::session_dir();
 
         if !session_dir.exists() {
             fs::create_dir_all(&session_dir)
@@ -125,3 +610,294 @@ impl Session {
         Ok(Cow::Owned(session_dir))
     }
 }
+
+fn session_file(
+    dir: &Path,
+    filename: &str,
+    format: SessionFormat,
+    compressed: bool,
+) -> PathBuf {
+    let extension = EXTENSIONS
+        .iter()
+        .find(|&&(candidate, gz, _)| (candidate, gz) == (format, compressed))
+        .map_or("json", |&(_, _, extension)| extension);
+
+    dir.join(filename).with_extension(extension)
+}
+
+fn push_jsonl_lines(
+    contents: &mut Vec<u8>,
+    messages: &[Message],
+) -> Result<(), SessionError> {
+    for msg in messages {
+        serde_json::to_writer(&mut *contents, msg)?;
+        contents.push(b'\n');
+    }
+
+    Ok(())
+}
+
+fn check_roles(messages: &[Message]) -> Result<(), SessionError> {
+    let mut started = false;
+    let mut previous: Option<&Message> = None;
+
+    for (index, msg) in messages.iter().enumerate() {
+        let invalid = |reason| SessionError::InvalidMessage {
+            index: index.saturating_add(1),
+            reason,
+        };
+
+        match msg.role {
+            Role::System if started => {
+                return Err(invalid(
+                    "system message after the conversation started",
+                ));
+            }
+            Role::System => {}
+            Role::Assistant
+                if previous.is_some_and(|prev| {
+                    prev.role == Role::Assistant
+                        && prev.tool.is_none()
+                        && msg.tool.is_none()
+                }) =>
+            {
+                return Err(invalid("two assistant replies in a row"));
+            }
+            Role::Tool if msg.tool.is_none() => {
+                return Err(invalid("tool message without a tool payload"));
+            }
+            Role::User | Role::Assistant | Role::Tool => started = true,
+        }
+
+        previous = Some(msg);
+    }
+
+    Ok(())
+}
+
+fn find_session_file(dir: &Path, filename: &str) -> Option<PathBuf> {
+    EXTENSIONS
+        .iter()
+        .map(|&(format, compressed, _)| {
+            session_file(dir, filename, format, compressed)
+        })
+        .find(|path| path.exists())
+}
+
+fn is_compressed(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(".gz")
+}
+
+fn format_of(path: &Path) -> SessionFormat {
+    let path = path.to_string_lossy();
+    if path.ends_with(".jsonl") || path.ends_with(".jsonl.gz") {
+        SessionFormat::Jsonl
+    } else {
+        SessionFormat::Json
+    }
+}
+
+fn compress(data: &[u8]) -> Result<Vec<u8>, SessionError> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).map_err(SessionError::Compress)?;
+    encoder.finish().map_err(SessionError::Compress)
+}
+
+fn decompress(data: &[u8]) -> Result<Vec<u8>, SessionError> {
+    let mut decompressed = Vec::new();
+    GzDecoder::new(data)
+        .read_to_end(&mut decompressed)
+        .map_err(SessionError::Decompress)?;
+    Ok(decompressed)
+}
+
+fn find_snippet(content: &str, query: &str) -> Option<String> {
+    let needle: Vec<char> =
+        query.chars().flat_map(char::to_lowercase).collect();
+    let chars: Vec<char> = content.chars().collect();
+    let position = (0..chars.len()).find(|&start| {
+        chars
+            .iter()
+            .skip(start)
+            .flat_map(|ch| ch.to_lowercase())
+            .take(needle.len())
+            .eq(needle.iter().copied())
+    })?;
+
+    let start = position.saturating_sub(SNIPPET_CONTEXT);
+    let end = position
+        .saturating_add(needle.len())
+        .saturating_add(SNIPPET_CONTEXT)
+        .min(chars.len());
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push_str("...");
+    }
+    snippet.extend(chars.iter().take(end).skip(start).map(|&ch| {
+        if ch.is_whitespace() {
+            ' '
+        } else {
+            ch
+        }
+    }));
+    if end < chars.len() {
+        snippet.push_str("...");
+    }
+
+    Some(snippet)
+}
+
+fn unix_seconds(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use core::iter;
+
+    use super::*;
+
+    #[test]
+    fn saves_pretty_json_and_loads_compact_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            session_path: Some(dir.path().to_path_buf()),
+            ..Config::default()
+        };
+
+        let mut session = Session::new();
+        session.add_message(Role::User, "Hi".to_owned());
+        session.add_message(Role::Assistant, "Hello!".to_owned());
+        session.save("pretty", &config).unwrap();
+
+        let pretty =
+            fs::read_to_string(dir.path().join("pretty.json")).unwrap();
+        assert!(pretty.lines().count() > 1, "{pretty}");
+
+        let compact = serde_json::to_string(&session).unwrap();
+        assert!(!compact.contains('\n'));
+        fs::write(dir.path().join("compact.json"), compact).unwrap();
+
+        for name in ["pretty", "compact"] {
+            let loaded = Session::load(name, &config).unwrap();
+            let contents: Vec<&str> = loaded
+                .conversation
+                .messages
+                .iter()
+                .map(|msg| msg.content.as_str())
+                .collect();
+            assert_eq!(contents, ["Hi", "Hello!"], "{name}");
+        }
+    }
+
+    #[test]
+    fn undo_history_is_bounded() {
+        let mut session = Session::new();
+        let turns = MAX_UNDO_STEPS + 5;
+        for turn in 0..turns {
+            session.add_message(Role::User, format!("Question {turn}"));
+            session.add_message(Role::Assistant, format!("Answer {turn}"));
+        }
+
+        for _ in 0..turns {
+            assert_eq!(session.undo(), Some(2));
+        }
+        assert!(session.conversation.messages.is_empty());
+
+        let redone = iter::from_fn(|| session.redo()).count();
+        assert_eq!(redone, MAX_UNDO_STEPS);
+        // The first undos, of the latest turns, were dropped.
+        assert_eq!(session.conversation.messages.len(), MAX_UNDO_STEPS * 2);
+        assert_eq!(
+            session.conversation.messages.last().map(|msg| &msg.content),
+            Some(&format!("Answer {}", MAX_UNDO_STEPS - 1)),
+        );
+    }
+
+    #[test]
+    fn undo_leaves_a_lone_system_prompt() {
+        let mut session = Session::new();
+        session
+            .conversation
+            .set_system_prompt(Some("Be brief.".to_owned()));
+
+        assert_eq!(session.undo(), None);
+        assert_eq!(contents(&session), ["Be brief."]);
+        assert_eq!(session.redo(), None);
+    }
+
+    #[test]
+    fn new_messages_clear_the_redo_stack() {
+        let mut session = Session::new();
+        session.add_message(Role::User, "Hi".to_owned());
+        session.add_message(Role::Assistant, "Hello!".to_owned());
+        assert_eq!(session.undo(), Some(2));
+
+        session.add_message(Role::User, "Hey".to_owned());
+
+        assert_eq!(session.redo(), None);
+        assert_eq!(contents(&session), ["Hey"]);
+    }
+
+    fn contents(session: &Session) -> Vec<&str> {
+        session
+            .conversation
+            .messages
+            .iter()
+            .map(|msg| msg.content.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn jsonl_saves_append_new_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            session_path: Some(dir.path().to_path_buf()),
+            session_format: Some(SessionFormat::Jsonl),
+            ..Config::default()
+        };
+        let path = dir.path().join("chat.jsonl");
+
+        let mut session = Session::new();
+        session.add_message(Role::User, "Hi".to_owned());
+        session.save("chat", &config).unwrap();
+        // Left alone by an append, replaced by a rewrite.
+        let marked =
+            fs::read_to_string(&path).unwrap().replacen("{}", "{ }", 1);
+        fs::write(&path, marked).unwrap();
+
+        session.add_message(Role::Assistant, "Hello!".to_owned());
+        session.save("chat", &config).unwrap();
+        let appended = fs::read_to_string(&path).unwrap();
+        assert!(appended.starts_with("{ }\n"), "{appended}");
+        assert_eq!(appended.lines().count(), 3, "{appended}");
+        assert_eq!(
+            contents(&Session::load("chat", &config).unwrap()),
+            ["Hi", "Hello!"],
+        );
+
+        session
+            .conversation
+            .set_system_prompt(Some("Be brief.".to_owned()));
+        session.save("chat", &config).unwrap();
+        let rewritten = fs::read_to_string(&path).unwrap();
+        assert!(rewritten.starts_with("{}\n"), "{rewritten}");
+        assert_eq!(
+            contents(&Session::load("chat", &config).unwrap()),
+            ["Be brief.", "Hi", "Hello!"],
+        );
+    }
+
+    #[test]
+    fn jsonl_drops_a_truncated_last_line() {
+        let jsonl =
+            b"{}\n{\"role\":\"user\",\"content\":\"Hi\"}\n{\"role\":\"assi";
+
+        let session = Session::from_jsonl(jsonl).unwrap();
+
+        assert_eq!(contents(&session), ["Hi"]);
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    env,
+    io::{self, IsTerminal as _},
+    sync::{Mutex, PoisonError},
+};
+
+use aes_gcm::{
+    aead::{rand_core::RngCore as _, Aead as _, AeadCore as _, OsRng},
+    Aes256Gcm, Key, KeyInit as _, Nonce,
+};
+use argon2::Argon2;
+use dialoguer::Password;
+
+use super::SessionError;
+
+const MAGIC: &[u8] = b"LLMCLI-ENC1\n";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const PASSPHRASE_ENV: &str = "LLMCLI_SESSION_PASSPHRASE";
+
+/// The last passphrase known to be right, so it is asked for once per run.
+static PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+
+pub(super) fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub(super) fn encrypt(plaintext: &[u8]) -> Result<Vec<u8>, SessionError> {
+    let mut salt = [0_u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let passphrase = passphrase(true)?;
+    let key = derive_key(&passphrase, &salt)?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+    #[expect(
+        clippy::map_err_ignore,
+        reason = r#"
+            `aes_gcm::Error` is opaque and carries no details worth showing.
+        "#
+    )]
+    let ciphertext = Aes256Gcm::new(&key)
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| SessionError::Encrypt)?;
+
+    let mut encrypted = Vec::with_capacity(
+        MAGIC
+            .len()
+            .saturating_add(SALT_LEN)
+            .saturating_add(NONCE_LEN)
+            .saturating_add(ciphertext.len()),
+    );
+    encrypted.extend_from_slice(MAGIC);
+    encrypted.extend_from_slice(&salt);
+    encrypted.extend_from_slice(&nonce);
+    encrypted.extend_from_slice(&ciphertext);
+    remember(passphrase);
+
+    Ok(encrypted)
+}
+
+pub(super) fn decrypt(data: &[u8]) -> Result<Vec<u8>, SessionError> {
+    let payload = data.strip_prefix(MAGIC).ok_or(SessionError::Decrypt)?;
+    let (salt, payload) = payload
+        .split_at_checked(SALT_LEN)
+        .ok_or(SessionError::Decrypt)?;
+    let (nonce, ciphertext) = payload
+        .split_at_checked(NONCE_LEN)
+        .ok_or(SessionError::Decrypt)?;
+    let passphrase = passphrase(false)?;
+    let key = derive_key(&passphrase, salt)?;
+
+    #[expect(
+        clippy::map_err_ignore,
+        reason = r#"
+            A failed decryption means a wrong passphrase or a corrupted file,
+            `aes_gcm::Error` cannot tell the two apart.
+        "#
+    )]
+    let decrypted = Aes256Gcm::new(&key)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| SessionError::Decrypt);
+
+    // A wrong passphrase must not stick around for the next attempt.
+    match decrypted {
+        Ok(_) => remember(passphrase),
+        Err(_) => forget(),
+    }
+
+    decrypted
+}
+
+fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+) -> Result<Key<Aes256Gcm>, SessionError> {
+    let mut key = Key::<Aes256Gcm>::default();
+
+    #[expect(
+        clippy::map_err_ignore,
+        reason = r#"
+            Key derivation only fails on invalid parameters, which are fixed.
+        "#
+    )]
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| SessionError::Encrypt)?;
+
+    Ok(key)
+}
+
+/// Reads the passphrase from the environment, the cache or a prompt. With
+/// `confirm` a new passphrase has to be typed twice, since a typo would lock
+/// the session away for good.
+fn passphrase(confirm: bool) -> Result<String, SessionError> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+    let cached = PASSPHRASE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    if let Some(passphrase) = cached {
+        return Ok(passphrase);
+    }
+    if !io::stdin().is_terminal() {
+        return Err(SessionError::PassphraseMissing);
+    }
+
+    let mut prompt = Password::new().with_prompt("Session passphrase");
+    if confirm {
+        prompt = prompt.with_confirmation(
+            "Repeat passphrase",
+            "Passphrases do not match.",
+        );
+    }
+
+    prompt.interact().map_err(SessionError::Passphrase)
+}
+
+fn remember(passphrase: String) {
+    *PASSPHRASE.lock().unwrap_or_else(PoisonError::into_inner) =
+        Some(passphrase);
+}
+
+fn forget() {
+    *PASSPHRASE.lock().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{config::Config, session::Session, Role};
+
+    #[test]
+    fn encrypts_and_decrypts_with_the_env_passphrase() {
+        env::set_var(PASSPHRASE_ENV, "correct horse");
+        let encrypted = encrypt(b"secret session").unwrap();
+
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted
+            .windows(b"secret".len())
+            .any(|window| window == b"secret"));
+        assert_eq!(decrypt(&encrypted).unwrap(), b"secret session");
+
+        env::set_var(PASSPHRASE_ENV, "battery staple");
+        let result = decrypt(&encrypted);
+        env::remove_var(PASSPHRASE_ENV);
+
+        assert!(matches!(result, Err(SessionError::Decrypt)));
+    }
+
+    #[test]
+    fn plaintext_sessions_still_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            session_path: Some(dir.path().to_path_buf()),
+            ..Config::default()
+        };
+        let mut session = Session::new();
+        session.add_message(Role::User, "Hi".to_owned());
+        let plaintext = serde_json::to_vec(&session).unwrap();
+        assert!(!is_encrypted(&plaintext));
+        fs::write(dir.path().join("plain.json"), plaintext).unwrap();
+
+        let loaded = Session::load("plain", &config).unwrap();
+
+        assert_eq!(loaded.conversation.messages[0].content, "Hi");
+    }
+}
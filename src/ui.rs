@@ -1,50 +1,300 @@
-use std::io;
+use core::{
+    convert::Infallible,
+    future::{self, Future},
+    mem,
+};
+use std::{
+    env,
+    fs::OpenOptions,
+    io::{self, IsTerminal as _, Write as _},
+    path::PathBuf,
+    process::{Command, Stdio},
+};
 
+use chrono::Utc;
 use crossterm::{
+    event::{Event, EventStream, KeyCode, KeyEventKind, KeyModifiers},
     execute,
     style::{
         Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor,
     },
+    terminal,
+};
+use futures::StreamExt as _;
+use tokio::signal;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::{Config, PagerMode},
+    redact_secrets,
 };
 
+const DEFAULT_PAGER: &str = "less -R";
+const MODEL_PROMPT_MARKER: &str = "\u{276f}";
+
+struct Theme {
+    user_prefix: String,
+    user_color: Color,
+    app_prefix: String,
+    app_color: Color,
+    assistant_color: Color,
+    error_color: Color,
+    show_model: bool,
+}
+
+impl Theme {
+    fn from_config(config: &Config) -> Self {
+        let ui = config.ui.as_ref();
+
+        Self {
+            user_prefix: ui
+                .and_then(|ui| ui.user_prefix.clone())
+                .unwrap_or_else(|| "You:\n".to_owned()),
+            user_color: ui
+                .and_then(|ui| ui.user_color)
+                .unwrap_or(Color::Magenta),
+            app_prefix: ui
+                .and_then(|ui| ui.app_prefix.clone())
+                .unwrap_or_else(|| "llmcli:\n".to_owned()),
+            app_color: ui.and_then(|ui| ui.app_color).unwrap_or(Color::Blue),
+            assistant_color: ui
+                .and_then(|ui| ui.assistant_color)
+                .unwrap_or(Color::Cyan),
+            error_color: ui.and_then(|ui| ui.error_color).unwrap_or(Color::Red),
+            show_model: ui
+                .and_then(|ui| ui.show_model_in_prompt)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Formats a token count compactly, e.g. `950`, `2.1k` or `1.0M`.
+#[inline]
+#[must_use]
+#[expect(
+    clippy::integer_division,
+    clippy::integer_division_remainder_used,
+    reason = r#"
+        Truncating to one decimal place is intended for a rough indicator.
+    "#
+)]
+pub fn format_tokens(count: usize) -> String {
+    match count {
+        0..1_000 => count.to_string(),
+        1_000..1_000_000 => {
+            format!("{}.{}k", count / 1_000, count % 1_000 / 100)
+        }
+        _ => format!("{}.{}M", count / 1_000_000, count % 1_000_000 / 100_000),
+    }
+}
+
+#[inline]
+#[must_use]
+#[expect(
+    clippy::integer_division,
+    clippy::integer_division_remainder_used,
+    reason = r#"
+        Truncating to one decimal place is intended for a rough indicator.
+    "#
+)]
+pub fn format_bytes(size: u64) -> String {
+    const KIB: u64 = 1 << 10;
+    const MIB: u64 = 1 << 20;
+
+    match size {
+        0..KIB => format!("{size} B"),
+        KIB..MIB => format!(
+            "{}.{} KiB",
+            size / KIB,
+            (size % KIB).saturating_mul(10) / KIB
+        ),
+        _ => format!(
+            "{}.{} MiB",
+            size / MIB,
+            (size % MIB).saturating_mul(10) / MIB
+        ),
+    }
+}
+
+/// Collects streamed reply chunks and releases them on whitespace.
+///
+/// Printing every chunk as it arrives makes the output flicker and can split
+/// words across the wrap column, so text is held back until a word is
+/// complete and then wrapped the same way as [`Printer::print_reply`].
+#[non_exhaustive]
+#[derive(Default)]
+pub struct StreamBuffer {
+    pending: String,
+    column: usize,
+    in_code_block: bool,
+}
+
+impl StreamBuffer {
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(&mut self, chunk: &str, width: Option<usize>) -> String {
+        self.pending.push_str(chunk);
+
+        let Some((index, last)) = self
+            .pending
+            .char_indices()
+            .rfind(|&(_, ch)| ch.is_whitespace())
+        else {
+            return String::new();
+        };
+
+        let rest = self
+            .pending
+            .split_off(index.saturating_add(last.len_utf8()));
+        let ready = mem::replace(&mut self.pending, rest);
+
+        self.layout(&ready, width)
+    }
+
+    fn finish(&mut self, width: Option<usize>) -> String {
+        let rest = mem::take(&mut self.pending);
+        let text = self.layout(&rest, width);
+        self.column = 0;
+        self.in_code_block = false;
+        text
+    }
+
+    fn layout(&mut self, text: &str, width: Option<usize>) -> String {
+        let mut out = String::with_capacity(text.len());
+
+        for piece in text.split_inclusive('\n') {
+            let line = piece.strip_suffix('\n');
+
+            if self.column == 0 && piece.trim_start().starts_with("```") {
+                self.in_code_block = !self.in_code_block;
+            }
+
+            match width {
+                Some(width) if !self.in_code_block => {
+                    for word in line.unwrap_or(piece).split_inclusive(' ') {
+                        let len = word.trim_end().chars().count();
+                        let word = if self.column > 0
+                            && self.column.saturating_add(len) > width
+                        {
+                            out.push('\n');
+                            self.column = 0;
+                            word.trim_start()
+                        } else {
+                            word
+                        };
+                        out.push_str(word);
+                        self.column =
+                            self.column.saturating_add(word.chars().count());
+                    }
+                }
+                Some(_) | None => {
+                    out.push_str(line.unwrap_or(piece));
+                    self.column = self
+                        .column
+                        .saturating_add(line.unwrap_or(piece).chars().count());
+                }
+            }
+
+            if line.is_some() {
+                out.push('\n');
+                self.column = 0;
+            }
+        }
+
+        out
+    }
+}
+
 pub struct Printer {
     no_color: bool,
+    pager: PagerMode,
+    theme: Theme,
+    transcript: Option<PathBuf>,
+    show_reasoning: bool,
 }
 
 impl Printer {
     #[inline]
     #[must_use]
-    pub const fn new(no_color: bool) -> Self {
-        Self { no_color }
+    pub fn new(no_color: bool, config: &Config) -> Self {
+        Self {
+            no_color,
+            pager: config.use_pager.unwrap_or_default(),
+            theme: Theme::from_config(config),
+            transcript: config.transcript_log.clone(),
+            show_reasoning: config.show_reasoning.unwrap_or_default(),
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn no_color(&self) -> bool {
+        self.no_color
+    }
+
+    #[inline]
+    pub fn reconfigure(&mut self, config: &Config) {
+        self.pager = config.use_pager.unwrap_or_default();
+        self.theme = Theme::from_config(config);
+        self.transcript.clone_from(&config.transcript_log);
+        self.show_reasoning = config.show_reasoning.unwrap_or_default();
+    }
+
+    /// Flips whether reasoning is shown in full and returns the new state.
+    #[inline]
+    pub const fn toggle_reasoning(&mut self) -> bool {
+        self.show_reasoning = !self.show_reasoning;
+        self.show_reasoning
     }
 
     #[inline]
     #[must_use]
     pub fn get_user_prefix(&self) -> String {
         if self.no_color {
-            "You:\n".to_owned()
+            self.theme.user_prefix.clone()
         } else {
             format!(
-                "{}{}You:{}{}\n",
-                SetForegroundColor(Color::Magenta),
+                "{}{}{}{}{}",
+                SetForegroundColor(self.theme.user_color),
                 SetAttribute(Attribute::Bold),
+                self.theme.user_prefix,
                 ResetColor,
                 SetAttribute(Attribute::Reset)
             )
         }
     }
 
+    #[inline]
+    #[must_use]
+    pub fn get_model_prompt(&self, model: &str) -> String {
+        if !self.theme.show_model {
+            String::new()
+        } else if self.no_color {
+            format!("{model} {MODEL_PROMPT_MARKER} ")
+        } else {
+            format!(
+                "{}{model} {MODEL_PROMPT_MARKER} {}",
+                SetForegroundColor(self.theme.assistant_color),
+                ResetColor
+            )
+        }
+    }
+
     #[inline]
     pub fn print_app_message(&self, message: &str) -> io::Result<()> {
         if self.no_color {
-            println!("llmcli:\n{message}");
+            println!("{}{message}", self.theme.app_prefix);
             Ok(())
         } else {
             execute!(
                 io::stdout(),
-                SetForegroundColor(Color::Blue),
+                SetForegroundColor(self.theme.app_color),
                 SetAttribute(Attribute::Bold),
-                Print("llmcli:\n"),
+                Print(&self.theme.app_prefix),
                 ResetColor,
                 SetAttribute(Attribute::Reset),
                 Print(message),
@@ -61,7 +311,7 @@ impl Printer {
         } else {
             execute!(
                 io::stdout(),
-                SetForegroundColor(Color::Cyan),
+                SetForegroundColor(self.theme.assistant_color),
                 SetAttribute(Attribute::Bold),
                 Print(name),
                 Print(":\n"),
@@ -71,15 +321,205 @@ impl Printer {
         }
     }
 
+    #[inline]
+    pub fn confirm(&self, question: &str) -> io::Result<bool> {
+        self.print_app_message(&format!("{question} [y/N]"))?;
+
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+
+        Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
+
+    #[inline]
+    pub fn print_reply(
+        &self,
+        text: &str,
+        markdown_disabled: bool,
+    ) -> io::Result<()> {
+        let rendered = if markdown_disabled {
+            Self::wrap_text(text)
+        } else {
+            termimad::term_text(text).to_string()
+        };
+
+        if self.should_page(&rendered) && Self::page(&rendered).is_ok() {
+            return Ok(());
+        }
+
+        write!(io::stdout(), "{rendered}")
+    }
+
+    /// Prints a reply's reasoning dimmed, or a one-line note about it while
+    /// reasoning is hidden.
+    #[inline]
+    pub fn print_reasoning(&self, reasoning: &str) -> io::Result<()> {
+        let text = if self.show_reasoning {
+            format!("{}\n", reasoning.trim())
+        } else {
+            format!(
+                "(Reasoned for ~{} tokens, /reasoning shows it.)\n",
+                reasoning.chars().count().div_ceil(4)
+            )
+        };
+
+        if self.no_color {
+            write!(io::stdout(), "{text}")
+        } else {
+            execute!(
+                io::stdout(),
+                SetAttribute(Attribute::Dim),
+                Print(text),
+                SetAttribute(Attribute::Reset),
+            )
+        }
+    }
+
+    #[inline]
+    pub fn print_wrapped(&self, text: &str) -> io::Result<()> {
+        write!(io::stdout(), "{}", Self::wrap_text(text))
+    }
+
+    /// Prints text untouched, for output meant to be copied such as JSON.
+    #[inline]
+    pub fn print_plain(&self, text: &str) -> io::Result<()> {
+        writeln!(io::stdout(), "{text}")
+    }
+
+    /// Prints the part of a streamed reply that is ready to be shown.
+    #[inline]
+    pub fn print_stream_chunk(
+        &self,
+        buffer: &mut StreamBuffer,
+        chunk: &str,
+    ) -> io::Result<()> {
+        let ready = buffer.push(chunk, Self::terminal_width());
+        let mut stdout = io::stdout();
+        // Chunks arrive while Esc is watched for in raw mode, where a bare
+        // `\n` moves down without returning to the first column.
+        if terminal::is_raw_mode_enabled().unwrap_or_default() {
+            write!(stdout, "{}", ready.replace('\n', "\r\n"))?;
+        } else {
+            write!(stdout, "{ready}")?;
+        }
+        stdout.flush()
+    }
+
+    #[inline]
+    pub fn finish_stream(&self, buffer: &mut StreamBuffer) -> io::Result<()> {
+        let rest = buffer.finish(Self::terminal_width());
+        let mut stdout = io::stdout();
+        writeln!(stdout, "{rest}")?;
+        stdout.flush()
+    }
+
+    fn terminal_width() -> Option<usize> {
+        match terminal::size() {
+            Ok((columns, _)) if io::stdout().is_terminal() => {
+                Some(usize::from(columns))
+            }
+            _ => None,
+        }
+    }
+
+    fn wrap_text(text: &str) -> String {
+        let Some(width) = Self::terminal_width() else {
+            return text.to_owned();
+        };
+
+        let mut wrapped_text = String::with_capacity(text.len());
+        let mut in_code_block = false;
+        for line in text.lines() {
+            if line.trim_start().starts_with("```") {
+                in_code_block = !in_code_block;
+                wrapped_text.push_str(line);
+                wrapped_text.push('\n');
+            } else if in_code_block {
+                wrapped_text.push_str(line);
+                wrapped_text.push('\n');
+            } else {
+                for wrapped in textwrap::wrap(line, width) {
+                    wrapped_text.push_str(&wrapped);
+                    wrapped_text.push('\n');
+                }
+            }
+        }
+
+        wrapped_text
+    }
+
+    fn should_page(&self, text: &str) -> bool {
+        if self.no_color || !io::stdout().is_terminal() {
+            return false;
+        }
+
+        match self.pager {
+            PagerMode::Never => false,
+            PagerMode::Always => true,
+            PagerMode::Auto => terminal::size().is_ok_and(|(_, rows)| {
+                text.lines().count() > usize::from(rows)
+            }),
+        }
+    }
+
+    fn page(text: &str) -> io::Result<()> {
+        let pager = env::var("PAGER")
+            .ok()
+            .filter(|pager| !pager.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_PAGER.to_owned());
+        let mut parts = pager.split_whitespace();
+        let program = parts.next().unwrap_or(DEFAULT_PAGER);
+
+        let mut command = Command::new(program);
+        command.args(parts).stdin(Stdio::piped());
+        if env::var_os("LESS").is_none() {
+            command.env("LESS", "R");
+        }
+
+        let mut child = command.spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            match stdin.write_all(text.as_bytes()) {
+                Err(err) if err.kind() != io::ErrorKind::BrokenPipe => {
+                    return Err(err);
+                }
+                _ => {}
+            }
+        }
+
+        child.wait()?;
+
+        Ok(())
+    }
+
+    /// Appends the raw, unrendered text to the transcript log, if one is set.
+    #[inline]
+    pub fn log_transcript(&self, speaker: &str, text: &str) -> io::Result<()> {
+        let Some(path) = self.transcript.as_ref() else {
+            return Ok(());
+        };
+
+        let mut file =
+            OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(
+            file,
+            "[{}] {speaker}:\n{}\n",
+            Utc::now().format("%Y-%m-%d %H:%M:%S UTC"),
+            text.trim_end()
+        )
+    }
+
     #[inline]
     pub fn print_error_message(&self, message: &str) -> io::Result<()> {
+        let message = redact_secrets(message);
+
         if self.no_color {
             println!("Error:\n{message}");
             Ok(())
         } else {
             execute!(
                 io::stdout(),
-                SetForegroundColor(Color::Red),
+                SetForegroundColor(self.theme.error_color),
                 SetAttribute(Attribute::Bold),
                 Print("Error:\n"),
                 ResetColor,
@@ -90,3 +530,152 @@ impl Printer {
         }
     }
 }
+
+struct RawModeGuard;
+
+impl RawModeGuard {
+    fn enable() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawModeGuard {
+    #[inline]
+    fn drop(&mut self) {
+        if let Err(err) = terminal::disable_raw_mode() {
+            eprintln!("Failed to restore terminal: {err}");
+        }
+    }
+}
+
+#[inline]
+pub async fn wait_for_cancel() {
+    if !io::stdin().is_terminal() {
+        return wait_for_ctrl_c().await;
+    }
+
+    let Ok(_guard) = RawModeGuard::enable() else {
+        return wait_for_ctrl_c().await;
+    };
+
+    let mut events = EventStream::new();
+    while let Some(Ok(event)) = events.next().await {
+        if let Event::Key(key) = event {
+            let is_ctrl_c = key.code == KeyCode::Char('c')
+                && key.modifiers.contains(KeyModifiers::CONTROL);
+            if key.kind == KeyEventKind::Press
+                && (key.code == KeyCode::Esc || is_ctrl_c)
+            {
+                return;
+            }
+        }
+    }
+
+    future::pending::<()>().await;
+}
+
+/// Runs `request`, cancelling `cancel` if Esc or Ctrl+C is pressed before it
+/// finishes. `request` is expected to return soon after `cancel` fires.
+#[inline]
+#[expect(
+    clippy::integer_division_remainder_used,
+    reason = r#"
+        `tokio::select!` uses `%` internally to pick a random branch.
+    "#
+)]
+pub async fn cancel_on_keypress<F: Future>(
+    cancel: &CancellationToken,
+    request: F,
+) -> F::Output {
+    let watcher = async {
+        wait_for_cancel().await;
+        cancel.cancel();
+        future::pending::<Infallible>().await
+    };
+
+    tokio::select! {
+        biased;
+        output = request => output,
+        never = watcher => match never {},
+    }
+}
+
+async fn wait_for_ctrl_c() {
+    if signal::ctrl_c().await.is_err() {
+        future::pending::<()>().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Streams `chunks` through a fresh buffer and returns everything printed.
+    fn stream(chunks: &[&str], width: Option<usize>) -> String {
+        let mut buffer = StreamBuffer::new();
+        let mut out: String = chunks
+            .iter()
+            .map(|chunk| buffer.push(chunk, width))
+            .collect();
+        out.push_str(&buffer.finish(width));
+        out
+    }
+
+    #[test]
+    fn split_words_wait_for_whitespace() {
+        let mut buffer = StreamBuffer::new();
+
+        assert_eq!(buffer.push("Hel", Some(80)), "");
+        assert_eq!(buffer.push("lo wor", Some(80)), "Hello ");
+        assert_eq!(buffer.push("ld", Some(80)), "");
+        assert_eq!(buffer.finish(Some(80)), "world");
+    }
+
+    #[test]
+    fn wraps_at_the_width() {
+        assert_eq!(
+            stream(&["The quick ", "brown fox ", "jumps"], Some(10)),
+            "The quick \nbrown fox \njumps",
+        );
+        assert_eq!(
+            stream(&["The quick ", "brown fox ", "jumps"], None),
+            "The quick brown fox jumps",
+        );
+    }
+
+    #[test]
+    fn long_words_get_their_own_line() {
+        assert_eq!(
+            stream(&["a ", "abcdefghij ", "b"], Some(5)),
+            "a \nabcdefghij \nb",
+        );
+    }
+
+    #[test]
+    fn code_blocks_are_not_wrapped() {
+        assert_eq!(
+            stream(
+                &["Code:\n``", "`\nlet value = 1;\n", "```\nafter text here"],
+                Some(10),
+            ),
+            "Code:\n```\nlet value = 1;\n```\nafter text \nhere",
+        );
+    }
+
+    #[test]
+    fn formats_token_counts() {
+        assert_eq!(format_tokens(950), "950");
+        assert_eq!(format_tokens(2_150), "2.1k");
+        assert_eq!(format_tokens(999_999), "999.9k");
+        assert_eq!(format_tokens(1_000_000), "1.0M");
+    }
+
+    #[test]
+    fn formats_byte_sizes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1_536), "1.5 KiB");
+        assert_eq!(format_bytes(256 << 10), "256.0 KiB");
+        assert_eq!(format_bytes(1 << 20), "1.0 MiB");
+    }
+}
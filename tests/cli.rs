@@ -0,0 +1,172 @@
+#![expect(
+    clippy::tests_outside_test_module,
+    clippy::unwrap_used,
+    reason = r#"
+        Integration tests are their own crate, so the in-test allowances from
+        clippy.toml do not reach them.
+    "#
+)]
+
+use std::{fs, path::PathBuf};
+
+use assert_cmd::{assert::Assert, Command};
+use tempfile::TempDir;
+
+struct Sandbox {
+    dir: TempDir,
+}
+
+impl Sandbox {
+    fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("config.toml"), "use_pager = \"never\"\n")
+            .unwrap();
+        Self { dir }
+    }
+
+    fn session_dir(&self) -> PathBuf {
+        self.dir.path().join("sessions")
+    }
+
+    fn script(&self, replies: &str) -> PathBuf {
+        let path = self.dir.path().join("script.txt");
+        fs::write(&path, replies).unwrap();
+        path
+    }
+
+    fn llmcli(&self) -> Command {
+        let mut cmd = Command::cargo_bin("llmcli").unwrap();
+        cmd.env("LLMCLI_SESSION_DIR", self.session_dir())
+            .env("XDG_DATA_HOME", self.dir.path().join("data"))
+            .env("XDG_CACHE_HOME", self.dir.path().join("cache"))
+            .env_remove("LLMCLI_CONFIG_PATH")
+            .env_remove("DUMMY_SCRIPT")
+            .arg("--no-color")
+            .arg("--ephemeral")
+            .arg("--config")
+            .arg(self.dir.path().join("config.toml"));
+        cmd
+    }
+
+    fn configure(&self, config: &str) {
+        fs::write(self.dir.path().join("config.toml"), config).unwrap();
+    }
+
+    fn repl(&self, stdin: &str) -> Assert {
+        self.llmcli().arg("dummy").write_stdin(stdin).assert()
+    }
+}
+
+fn stdout(assert: &Assert) -> String {
+    String::from_utf8_lossy(&assert.get_output().stdout).into_owned()
+}
+
+#[test]
+fn help_lists_the_commands() {
+    let sandbox = Sandbox::new();
+    let assert = sandbox.repl("/help\n/quit\n").success();
+    let out = stdout(&assert);
+
+    assert!(out.contains("Available commands:"), "{out}");
+    for command in ["/clear", "/save", "/load", "/sessions", "/quit"] {
+        assert!(out.contains(command), "missing {command} in {out}");
+    }
+}
+
+#[test]
+fn quit_exits_successfully() {
+    let sandbox = Sandbox::new();
+    let assert = sandbox.repl("/quit\n").success();
+    let out = stdout(&assert);
+
+    assert!(out.contains("Quitting..."), "{out}");
+}
+
+#[test]
+fn replies_come_from_the_dummy_script() {
+    let sandbox = Sandbox::new();
+    let script = sandbox.script("Scripted hello.\n");
+    let assert = sandbox
+        .llmcli()
+        .env("DUMMY_SCRIPT", script)
+        .arg("dummy")
+        .write_stdin("hi\n")
+        .assert()
+        .success();
+    let out = stdout(&assert);
+
+    assert!(out.contains("Scripted hello."), "{out}");
+}
+
+#[test]
+fn clear_keeps_the_system_prompt_unless_asked() {
+    let sandbox = Sandbox::new();
+    let assert = sandbox
+        .llmcli()
+        .args(["--system-prompt", "Be brief.", "dummy"])
+        .write_stdin("/clear\n/system\n/clear all\n/system\n/quit\n")
+        .assert()
+        .success();
+    let out = stdout(&assert);
+
+    assert!(out.contains("Conversation cleared."), "{out}");
+    assert!(out.contains("System prompt: Be brief."), "{out}");
+    assert!(
+        out.contains("Conversation and system prompt cleared."),
+        "{out}"
+    );
+    assert!(out.contains("No system prompt set."), "{out}");
+}
+
+#[test]
+fn sessions_are_saved_to_the_session_dir_override() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .llmcli()
+        .args(["--system-prompt", "Be brief.", "dummy"])
+        .write_stdin("/save demo\n/quit\n")
+        .assert()
+        .success();
+
+    let saved =
+        fs::read_to_string(sandbox.session_dir().join("demo.json")).unwrap();
+    assert!(saved.contains("Be brief."), "{saved}");
+}
+
+#[test]
+fn saved_sessions_can_be_listed_and_loaded() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .llmcli()
+        .args(["--system-prompt", "Be brief.", "dummy"])
+        .write_stdin("/save demo\n/quit\n")
+        .assert()
+        .success();
+
+    let assert = sandbox
+        .repl("/sessions\n/load demo\n/system\n/quit\n")
+        .success();
+    let out = stdout(&assert);
+    assert!(out.contains("Saved sessions:"), "{out}");
+    assert!(out.contains("\tdemo"), "{out}");
+    assert!(out.contains("Session loaded from demo"), "{out}");
+    assert!(out.contains("System prompt: Be brief."), "{out}");
+
+    let assert = sandbox.llmcli().arg("sessions").assert().success();
+    assert_eq!(stdout(&assert).trim(), "demo");
+}
+
+#[test]
+fn loading_keeps_a_session_without_a_system_prompt() {
+    let sandbox = Sandbox::new();
+    sandbox.repl("/save bare\n/quit\n").success();
+    sandbox.configure(
+        "use_pager = \"never\"\ndefault_system_prompt = \"Be brief.\"\n",
+    );
+
+    let assert = sandbox.repl("/load bare\n/system\n/quit\n").success();
+    let out = stdout(&assert);
+
+    assert!(out.contains("Session loaded from bare"), "{out}");
+    assert!(out.contains("No system prompt set."), "{out}");
+}